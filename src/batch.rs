@@ -0,0 +1,100 @@
+//! Batch requests: `multipart/mixed` forms whose parts are whole HTTP requests.
+
+use http_types::{format_err, Request, Result};
+
+use crate::Multipart;
+
+impl Multipart<'_> {
+    /// Adds a serialized HTTP request as an `application/http` part, as used by batch endpoints.
+    ///
+    /// The part holds the request line (with the path and query of the URL), the request headers,
+    /// and the body, which is read from `req`. `content_id` is sent as the part's `Content-ID`, which
+    /// batch APIs use to match responses to requests. The form must be `multipart/mixed`, see
+    /// [`Multipart::mixed`]. Returns an error for other subtypes, or if the body can't be read.
+    pub async fn add_http_request(
+        &mut self,
+        req: &mut Request,
+        content_id: Option<&str>,
+    ) -> Result<()> {
+        if self.subtype != "mixed" {
+            return Err(format_err!(
+                "HTTP request parts need a multipart/mixed form, not multipart/{}",
+                self.subtype
+            ));
+        }
+        let mut part = b"Content-Type: application/http\r\n".to_vec();
+        if let Some(content_id) = content_id {
+            part.extend(format!("Content-ID: <{}>\r\n", content_id).into_bytes());
+        }
+        part.extend(b"\r\n");
+        part.extend(serialize_request(req).await?);
+        self.append_raw(part);
+        Ok(())
+    }
+}
+
+/// Serializes a request as it would be sent over HTTP/1.1, consuming its body.
+async fn serialize_request(req: &mut Request) -> Result<Vec<u8>> {
+    let url = req.url();
+    let mut target = url.path().to_owned();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    let mut serialized = format!("{} {} HTTP/1.1\r\n", req.method(), target);
+    let mut headers = req.iter().collect::<Vec<_>>();
+    // Header maps don't keep insertion order, sorting keeps the output stable
+    headers.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+    for (name, values) in headers {
+        for value in values {
+            serialized.push_str(&format!("{}: {}\r\n", name, value));
+        }
+    }
+    serialized.push_str("\r\n");
+    let mut serialized = serialized.into_bytes();
+    serialized.extend(req.take_body().into_bytes().await?);
+    Ok(serialized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_types::{Method, Url};
+
+    #[async_std::test]
+    async fn test_batch_of_http_requests() -> Result<()> {
+        let mut batch = Multipart::mixed();
+        let url = Url::parse("https://example.com/farm/v1/animals/pony")?;
+        let mut get = Request::new(Method::Get, url);
+        batch.add_http_request(&mut get, Some("item1")).await?;
+
+        let url = Url::parse("https://example.com/farm/v1/animals?fields=name")?;
+        let mut post = Request::new(Method::Post, url);
+        post.insert_header("Content-Type", "application/json");
+        post.set_body(r#"{"name":"sheep"}"#);
+        batch.add_http_request(&mut post, None).await?;
+
+        let content_type = batch.content_type();
+        let boundary = content_type
+            .strip_prefix("multipart/mixed; boundary=")
+            .unwrap()
+            .to_owned();
+        let body = String::from_utf8(batch.into_bytes().await?).unwrap();
+        let expected = "--{b}\r\n\
+            Content-Type: application/http\r\n\
+            Content-ID: <item1>\r\n\r\n\
+            GET /farm/v1/animals/pony HTTP/1.1\r\n\r\n\
+            \r\n--{b}\r\n\
+            Content-Type: application/http\r\n\r\n\
+            POST /farm/v1/animals?fields=name HTTP/1.1\r\n\
+            content-type: application/json\r\n\r\n\
+            {\"name\":\"sheep\"}\
+            \r\n--{b}--\r\n";
+        assert_eq!(body, expected.replace("{b}", &boundary));
+
+        let mut form = Multipart::new();
+        let mut req = Request::new(Method::Get, Url::parse("https://example.com")?);
+        assert!(form.add_http_request(&mut req, None).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+use std::{borrow::Cow, path::Path};
+
+use http_types::Result;
+
+use crate::{Encoding, Multipart};
+
+/// A builder for [`Multipart`] forms, created with [`Multipart::builder`].
+///
+/// ```
+/// use http_client_multipart::Multipart;
+///
+/// # async fn run() -> http_types::Result<()> {
+/// let form = Multipart::builder()
+///     .subtype("mixed")?
+///     .text("name", "John Doe")
+///     .file("avatar", "Cargo.toml")
+///     .await?
+///     .build();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct MultipartBuilder<'m> {
+    form: Multipart<'m>,
+}
+
+impl<'m> Multipart<'m> {
+    /// Creates a builder for a `multipart/form-data` form with a randomly generated boundary.
+    pub fn builder() -> MultipartBuilder<'m> {
+        MultipartBuilder::default()
+    }
+}
+
+impl<'m> MultipartBuilder<'m> {
+    /// Sets the subtype of the form, see [`Multipart::with_subtype`].
+    pub fn subtype(mut self, subtype: &str) -> Result<Self> {
+        self.form = self.form.with_subtype(subtype)?;
+        Ok(self)
+    }
+
+    /// Sets the boundary of the form, see [`Multipart::with_boundary`].
+    pub fn boundary(mut self, boundary: &str) -> Result<Self> {
+        self.form = self.form.with_boundary(boundary)?;
+        Ok(self)
+    }
+
+    /// Sets the chunk size used when the form is sent, see [`Multipart::with_buf_size`].
+    pub fn buf_size(mut self, buf_size: usize) -> Self {
+        self.form = self.form.with_buf_size(buf_size);
+        self
+    }
+
+    /// Sets the transfer encoding of the files added afterwards,
+    /// see [`Multipart::with_default_file_encoding`].
+    pub fn file_encoding(mut self, encoding: Encoding) -> Self {
+        self.form = self.form.with_default_file_encoding(encoding);
+        self
+    }
+
+    /// Adds a text field.
+    pub fn text(mut self, name: impl Into<Cow<'m, str>>, value: impl AsRef<str>) -> Self {
+        self.form.add_text(name, value);
+        self
+    }
+
+    /// Adds a file field from a path, see [`Multipart::add_file`].
+    pub async fn file(
+        mut self,
+        name: impl Into<Cow<'m, str>>,
+        path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        self.form.add_file(name, path, None).await?;
+        Ok(self)
+    }
+
+    /// Returns the built form.
+    pub fn build(self) -> Multipart<'m> {
+        self.form
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_types::{Method, Request, Url};
+
+    #[async_std::test]
+    async fn test_builder_mixed_form() -> Result<()> {
+        let form = Multipart::builder()
+            .subtype("mixed")?
+            .boundary("builder-boundary")?
+            .buf_size(16)
+            .text("name", "John Doe")
+            .file("manifest", "Cargo.toml")
+            .await?
+            .build();
+        assert_eq!(form.len(), 2);
+
+        let mut req = Request::new(Method::Post, Url::parse("http://example.com")?);
+        form.set_request(&mut req);
+        assert_eq!(
+            req["Content-Type"],
+            "multipart/mixed; boundary=builder-boundary"
+        );
+        let body = req.body_string().await?;
+        assert!(body.starts_with("--builder-boundary\r\n"));
+        assert!(body.contains("John Doe"));
+        assert!(body.contains("[package]"));
+        assert!(body.ends_with("\r\n--builder-boundary--\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_boundary() {
+        assert!(Multipart::builder().boundary("").is_err());
+        assert!(Multipart::builder().boundary("has space").is_err());
+        assert!(Multipart::builder().boundary(&"a".repeat(71)).is_err());
+    }
+}
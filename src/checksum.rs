@@ -0,0 +1,202 @@
+//! Checksums of form contents, computed while streaming or ahead of time.
+
+#[cfg(feature = "crc")]
+use std::sync::{Arc, OnceLock};
+
+#[cfg(feature = "crc")]
+use futures_lite::{Stream, StreamExt};
+#[cfg(feature = "sha256")]
+use http_types::{format_err, Result};
+
+use crate::Multipart;
+#[cfg(feature = "crc")]
+use crate::StreamChunk;
+
+/// A handle to the CRC32 of a streamed body, available once the stream has ended.
+///
+/// Returned by [`Multipart::into_stream_with_crc32`].
+#[cfg(feature = "crc")]
+#[derive(Debug, Clone, Default)]
+pub struct Crc32Handle(Arc<OnceLock<u32>>);
+
+#[cfg(feature = "crc")]
+impl Crc32Handle {
+    /// Returns the CRC32 of the whole body, or `None` if the stream hasn't been fully consumed.
+    pub fn get(&self) -> Option<u32> {
+        self.0.get().copied()
+    }
+}
+
+#[cfg(feature = "crc")]
+impl Multipart<'_> {
+    /// Converts the multipart form into a stream of byte chunks, computing the CRC32 of the body as it streams.
+    ///
+    /// The checksum is resolved in the returned handle when the stream ends. It is never set if the stream
+    /// fails or is dropped early.
+    pub fn into_stream_with_crc32(
+        self,
+        buf_size: Option<usize>,
+    ) -> (impl Stream<Item = StreamChunk>, Crc32Handle) {
+        let handle = Crc32Handle::default();
+        let crc = handle.0.clone();
+        let stream = self.into_stream(buf_size);
+        let state = (stream, crc32fast::Hasher::new(), crc);
+        let stream = futures_lite::stream::unfold(Some(state), |state| async move {
+            let (mut stream, mut hasher, crc) = state?;
+            match stream.next().await {
+                Some(Ok(chunk)) => {
+                    hasher.update(&chunk);
+                    Some((Ok(chunk), Some((stream, hasher, crc))))
+                }
+                Some(Err(e)) => Some((Err(e), None)),
+                None => {
+                    let _ = crc.set(hasher.finalize());
+                    None
+                }
+            }
+        });
+        (stream, handle)
+    }
+}
+
+#[cfg(feature = "sha256")]
+impl Multipart<'_> {
+    /// Appends a JSON part named `name` mapping each field name to the SHA-256 of its data.
+    ///
+    /// The hashes are computed from the parts added so far, over the data before any transfer encoding,
+    /// and written as lowercase hex. Parts without a name are listed under their filename.
+    /// Returns an error if a part is backed by a reader, which can't be hashed without consuming it,
+    /// or if two parts share a name.
+    pub fn add_checksum_manifest(&mut self, name: &str) -> Result<()> {
+        use sha2::{Digest, Sha256};
+
+        let mut keys = Vec::new();
+        let mut manifest = String::from("{");
+        for part in self.parts() {
+            let key = part
+                .name()
+                .or(part.filename())
+                .ok_or_else(|| format_err!("Cannot list a part without a name in the manifest"))?;
+            let data = part.data.as_bytes().ok_or_else(|| {
+                format_err!("Part `{}` is backed by a reader and can't be hashed", key)
+            })?;
+            if keys.contains(&key) {
+                return Err(format_err!("Duplicate part `{}` in the manifest", key));
+            }
+            keys.push(key);
+
+            if manifest.len() > 1 {
+                manifest.push(',');
+            }
+            let hash = Sha256::digest(data)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>();
+            manifest.push_str(&format!("{}:\"{}\"", json_string(key), hash));
+        }
+        manifest.push('}');
+        self.add_text_typed(
+            name.to_owned(),
+            manifest,
+            mime_guess::mime::APPLICATION_JSON,
+            None,
+            None,
+        )
+    }
+}
+
+/// Quotes a string as a JSON string literal.
+#[cfg(feature = "sha256")]
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn test_checksum_manifest() -> Result<()> {
+        use sha2::{Digest, Sha256};
+
+        let hex = |data: &[u8]| {
+            Sha256::digest(data)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        };
+        let mut m = Multipart::new();
+        m.add_file_bytes("a", "a.txt", b"first", "text/plain", None)?;
+        m.add_file_bytes("b\"", "b.bin", b"second", "application/octet-stream", None)?;
+        m.add_checksum_manifest("manifest")?;
+
+        let fields = m.text_fields();
+        assert_eq!(fields[0].0, "manifest");
+        assert_eq!(
+            fields[0].1,
+            format!(
+                "{{\"a\":\"{}\",\"b\\\"\":\"{}\"}}",
+                hex(b"first"),
+                hex(b"second")
+            )
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "sha256")]
+    #[async_std::test]
+    async fn test_checksum_manifest_rejects_readers() -> Result<()> {
+        let mut m = Multipart::new();
+        m.add_file("file", "Cargo.toml", None).await?;
+        assert!(m.add_checksum_manifest("manifest").is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "crc")]
+    #[async_std::test]
+    async fn test_streamed_crc32() -> http_types::Result<()> {
+        let mut form = Multipart::new().with_boundary("b")?;
+        form.add_text("field", "value");
+        form.add_file_bytes(
+            "f",
+            "a.bin",
+            b"hello world",
+            "application/octet-stream",
+            Some(crate::Encoding::Base64),
+        )?;
+        let expected = b"--b\r\n\
+            Content-Disposition: form-data; name=\"field\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            value\r\n\
+            --b\r\n\
+            Content-Disposition: form-data; name=\"f\"; filename=\"a.bin\"\r\n\
+            Content-Type: application/octet-stream\r\n\
+            Content-Transfer-Encoding: base64\r\n\r\n\
+            aGVsbG8gd29ybGQ=\r\n\
+            --b--\r\n";
+
+        let (stream, handle) = form.into_stream_with_crc32(Some(4));
+        futures_lite::pin!(stream);
+        let mut streamed = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            assert_eq!(handle.get(), None);
+            streamed.extend(chunk?);
+        }
+        assert_eq!(streamed, expected);
+        // CRC-32 of `expected`, as computed by e.g. Python's `zlib.crc32`
+        assert_eq!(handle.get(), Some(0xcca9_f07d));
+        Ok(())
+    }
+}
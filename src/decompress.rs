@@ -0,0 +1,154 @@
+//! Sending compressed files decompressed, using [`async-compression`](https://crates.io/crates/async-compression).
+
+use std::{borrow::Cow, path::Path};
+
+#[cfg(feature = "gzip")]
+use async_compression::futures::bufread::GzipDecoder;
+#[cfg(feature = "zstd")]
+use async_compression::futures::bufread::ZstdDecoder;
+use futures_lite::{io::BufReader, AsyncBufRead};
+use http_types::Result;
+
+use crate::{Encoding, Multipart};
+
+/// A compression format that files can be decompressed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Codec {
+    /// Gzip, usually stored with a `.gz` extension.
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// Zstandard, usually stored with a `.zst` extension.
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Codec {
+    /// Returns the file extension of the format, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            #[cfg(feature = "gzip")]
+            Codec::Gzip => "gz",
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => "zst",
+        }
+    }
+
+    /// Wraps a reader of compressed data into a reader of the decompressed data.
+    fn decoder(
+        self,
+        reader: impl AsyncBufRead + Unpin + Send + Sync + 'static,
+    ) -> Box<dyn AsyncBufRead + Unpin + Send + Sync> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Codec::Gzip => {
+                let mut decoder = GzipDecoder::new(reader);
+                // Concatenated gzip files are valid and decompress to the concatenated data
+                decoder.multiple_members(true);
+                Box::new(BufReader::new(decoder))
+            }
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => {
+                let mut decoder = ZstdDecoder::new(reader);
+                decoder.multiple_members(true);
+                Box::new(BufReader::new(decoder))
+            }
+        }
+    }
+}
+
+impl<'m> Multipart<'m> {
+    /// Adds a file field from a compressed file, sending its decompressed contents.
+    ///
+    /// The file is decompressed as the form streams, so it is never held in memory. The filename and
+    /// content type come from the path without its compression extension, so `notes.txt.gz` is sent as
+    /// `notes.txt` with `text/plain`. The decompressed length isn't known, so the form is sent without
+    /// a `Content-Length`. Returns an error if the file can't be opened; corrupt data fails the stream.
+    pub async fn add_file_decompressed(
+        &mut self,
+        name: impl Into<Cow<'m, str>>,
+        path: impl AsRef<Path>,
+        codec: Codec,
+        encoding: Option<Encoding>,
+    ) -> Result<()> {
+        let path = self.resolve_path(path.as_ref()).await?;
+        let file = async_fs::File::open(&path).await?;
+        let decompressed_path = match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case(codec.extension()) => {
+                path.with_extension("")
+            }
+            _ => path.to_path_buf(),
+        };
+        let filename = match decompressed_path.file_name() {
+            Some(filename) => filename.to_string_lossy().into_owned(),
+            None => self.default_filename.clone(),
+        };
+        let content_type = mime_guess::from_path(&filename).first_or_octet_stream();
+        let reader = codec.decoder(BufReader::new(file));
+        self.add_async_read(
+            name,
+            filename,
+            content_type.as_ref(),
+            encoding,
+            reader,
+            None,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::{io::Cursor, AsyncReadExt};
+
+    async fn compress(codec: Codec, data: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        match codec {
+            #[cfg(feature = "gzip")]
+            Codec::Gzip => async_compression::futures::bufread::GzipEncoder::new(Cursor::new(data))
+                .read_to_end(&mut compressed)
+                .await
+                .unwrap(),
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => async_compression::futures::bufread::ZstdEncoder::new(Cursor::new(data))
+                .read_to_end(&mut compressed)
+                .await
+                .unwrap(),
+        };
+        compressed
+    }
+
+    async fn check_decompressed(codec: Codec) -> Result<()> {
+        let original = "hello decompressed world\n".repeat(100);
+        let dir =
+            std::env::temp_dir().join(format!("decompress-{}", crate::random_alphanumeric(8)));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("notes.txt.{}", codec.extension()));
+        std::fs::write(&path, compress(codec, original.as_bytes()).await)?;
+
+        let mut form = Multipart::new();
+        form.add_file_decompressed("notes", &path, codec, None)
+            .await?;
+        assert_eq!(form.content_length(), None);
+        let body = String::from_utf8(form.into_bytes().await?).unwrap();
+        assert!(
+            body.contains("name=\"notes\"; filename=\"notes.txt\"\r\nContent-Type: text/plain\r\n")
+        );
+        assert!(body.contains(&format!("\r\n\r\n{}\r\n--", original)));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "gzip")]
+    #[async_std::test]
+    async fn test_add_file_decompressed_gzip() -> Result<()> {
+        check_decompressed(Codec::Gzip).await
+    }
+
+    #[cfg(feature = "zstd")]
+    #[async_std::test]
+    async fn test_add_file_decompressed_zstd() -> Result<()> {
+        check_decompressed(Codec::Zstd).await
+    }
+}
@@ -0,0 +1,143 @@
+use std::borrow::Cow;
+
+use http_types::{format_err, Result};
+
+use crate::multipart::is_token_char;
+
+/// The `Content-Disposition` header of a part.
+///
+/// Forms normally generate this header themselves (`form-data; name="..."`), this type is
+/// for parts that need a different disposition type or extra parameters.
+///
+/// ```
+/// use http_client_multipart::ContentDisposition;
+///
+/// let disposition = ContentDisposition::attachment()
+///     .with_filename("report.pdf")
+///     .with_param("size", "1024");
+/// assert_eq!(disposition.disposition_type(), "attachment");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentDisposition<'a> {
+    pub(crate) disposition_type: Cow<'a, str>,
+    pub(crate) name: Option<Cow<'a, str>>,
+    pub(crate) filename: Option<Cow<'a, str>>,
+    pub(crate) params: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+impl<'a> ContentDisposition<'a> {
+    /// Creates a disposition of the given type, such as `inline`, without any parameters.
+    pub fn new(disposition_type: impl Into<Cow<'a, str>>) -> Self {
+        Self {
+            disposition_type: disposition_type.into(),
+            name: None,
+            filename: None,
+            params: Vec::new(),
+        }
+    }
+
+    /// Creates a `form-data` disposition for the field `name`.
+    pub fn form_data(name: impl Into<Cow<'a, str>>) -> Self {
+        Self::new("form-data").with_name(name)
+    }
+
+    /// Creates an `attachment` disposition.
+    pub fn attachment() -> Self {
+        Self::new("attachment")
+    }
+
+    /// Sets the `name` parameter.
+    pub fn with_name(mut self, name: impl Into<Cow<'a, str>>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets the `filename` parameter.
+    pub fn with_filename(mut self, filename: impl Into<Cow<'a, str>>) -> Self {
+        self.filename = Some(filename.into());
+        self
+    }
+
+    /// Adds a parameter, written after `name` and `filename`.
+    ///
+    /// The value is quoted unless it is a valid token.
+    pub fn with_param(
+        mut self,
+        name: impl Into<Cow<'a, str>>,
+        value: impl Into<Cow<'a, str>>,
+    ) -> Self {
+        self.params.push((name.into(), value.into()));
+        self
+    }
+
+    /// Returns the disposition type, such as `form-data` or `attachment`.
+    pub fn disposition_type(&self) -> &str {
+        &self.disposition_type
+    }
+
+    /// Returns the `name` parameter.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the `filename` parameter.
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// Returns the extra parameters, in the order they were added.
+    pub fn params(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params
+            .iter()
+            .map(|(name, value)| (name.as_ref(), value.as_ref()))
+    }
+
+    /// Checks that the disposition type and parameter names are valid tokens,
+    /// and that `name` and `filename` are only set through their own methods.
+    pub(crate) fn validate(&self) -> Result<()> {
+        let is_token = |s: &str| !s.is_empty() && s.bytes().all(is_token_char);
+        if !is_token(&self.disposition_type) {
+            return Err(format_err!(
+                "Invalid disposition type `{}`",
+                self.disposition_type
+            ));
+        }
+        for (name, _) in &self.params {
+            if !is_token(name) {
+                return Err(format_err!("Invalid disposition parameter name `{}`", name));
+            }
+            if name.eq_ignore_ascii_case("name") || name.eq_ignore_ascii_case("filename") {
+                return Err(format_err!(
+                    "Disposition parameter `{}` must be set with its own method",
+                    name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(ContentDisposition::form_data("field").validate().is_ok());
+        assert!(ContentDisposition::new("inline")
+            .with_param("creation-date", "today, maybe")
+            .validate()
+            .is_ok());
+
+        assert!(ContentDisposition::new("").validate().is_err());
+        assert!(ContentDisposition::new("form data").validate().is_err());
+        assert!(ContentDisposition::attachment()
+            .with_param("bad name", "x")
+            .validate()
+            .is_err());
+        assert!(ContentDisposition::attachment()
+            .with_param("Filename", "x")
+            .validate()
+            .is_err());
+    }
+}
@@ -1,32 +1,437 @@
-use base64::Engine;
+use std::{io, str::FromStr};
 
-#[derive(Debug, Clone, Copy)]
+use base64::{
+    alphabet,
+    engine::{general_purpose, DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+
+/// Lenient engine used for decoding, accepting input with or without padding.
+const STANDARD_DECODE: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// The `Content-Transfer-Encoding` of a part.
+///
+/// Every way of serializing a form (`into_bytes`, `into_stream` and `into_reader`) encodes
+/// with the same alphabet, padding and line wrapping as [`encode_all`], so they produce the same bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Encoding {
     SevenBit,
     EightBit,
+    /// Base64 as specified by RFC 2045: the standard alphabet (`+` and `/`), with padding,
+    /// in lines of at most 76 characters separated by CRLF.
+    ///
+    /// Decoding accepts input with or without padding and line breaks.
     Base64,
+    /// Base64 with the URL-safe alphabet (`-` and `_` instead of `+` and `/`), without padding.
+    ///
+    /// Declared as `Content-Transfer-Encoding: base64`.
+    Base64UrlSafe,
+    /// Base64 with the URL-safe alphabet (`-` and `_` instead of `+` and `/`), with padding.
+    ///
+    /// Declared as `Content-Transfer-Encoding: base64`.
+    Base64UrlSafePadded,
     QuotedPrintable,
+    /// uuencoding, for legacy systems. Declared as `Content-Transfer-Encoding: x-uuencode`.
+    ///
+    /// The encoded data is framed by a `begin 644 file` line and an `end` line, with CRLF line endings.
+    /// The `begin` line always names `file`: the part's filename is sent in its `Content-Disposition`,
+    /// and a fixed line keeps the encoded length independent of the part.
+    #[cfg(feature = "uuencode")]
+    UuEncode,
 }
 
+/// The number of raw bytes encoded on each full line of [`Encoding::Base64`], 76 characters once encoded.
+pub(crate) const BASE64_LINE_LEN: usize = 57;
+
+/// The number of raw bytes encoded on each full uuencoded line.
+#[cfg(feature = "uuencode")]
+pub(crate) const UU_LINE_LEN: usize = 45;
+#[cfg(feature = "uuencode")]
+const UU_BEGIN: &[u8] = b"begin 644 file\r\n";
+#[cfg(feature = "uuencode")]
+const UU_END: &[u8] = b"`\r\nend\r\n";
+
 impl Encoding {
     pub fn to_str(self) -> &'static str {
         match self {
             Encoding::SevenBit => "7bit",
             Encoding::EightBit => "8bit",
-            Encoding::Base64 => "base64",
+            Encoding::Base64 | Encoding::Base64UrlSafe | Encoding::Base64UrlSafePadded => "base64",
             Encoding::QuotedPrintable => "quoted-printable",
+            #[cfg(feature = "uuencode")]
+            Encoding::UuEncode => "x-uuencode",
         }
     }
 
-    pub fn encode(self, input: &mut Vec<u8>) {
+    /// Returns whether this is one of the base64 variants.
+    pub(crate) fn is_base64(self) -> bool {
+        matches!(
+            self,
+            Encoding::Base64 | Encoding::Base64UrlSafe | Encoding::Base64UrlSafePadded
+        )
+    }
+
+    /// Returns the exact length of `raw_len` bytes once encoded.
+    ///
+    /// Returns `None` for quoted-printable, whose output length depends on the content.
+    pub fn encoded_len(self, raw_len: usize) -> Option<usize> {
+        self.encoded_len_u64(raw_len as u64)?.try_into().ok()
+    }
+
+    /// Like [`Encoding::encoded_len`], computed in `u64` so large lengths don't overflow on 32-bit targets.
+    ///
+    /// Also returns `None` if the encoded length doesn't fit in a `u64`.
+    pub(crate) fn encoded_len_u64(self, raw_len: u64) -> Option<u64> {
         match self {
             Encoding::Base64 => {
-                *input = base64::engine::general_purpose::STANDARD_NO_PAD
-                    .encode(&input)
-                    .into_bytes()
+                let encoded = raw_len.div_ceil(3).checked_mul(4)?;
+                // A CRLF between every two lines of 76 characters
+                let line_breaks = encoded.div_ceil(76).saturating_sub(1);
+                encoded.checked_add(line_breaks * 2)
+            }
+            Encoding::Base64UrlSafe => {
+                let tail = [0, 2, 3][(raw_len % 3) as usize];
+                (raw_len / 3).checked_mul(4)?.checked_add(tail)
+            }
+            Encoding::Base64UrlSafePadded => raw_len.div_ceil(3).checked_mul(4),
+            Encoding::QuotedPrintable => None,
+            Encoding::SevenBit | Encoding::EightBit => Some(raw_len),
+            #[cfg(feature = "uuencode")]
+            Encoding::UuEncode => {
+                let line_len = UU_LINE_LEN as u64;
+                let full_lines = (raw_len / line_len).checked_mul(3 + line_len / 3 * 4)?;
+                let rest = match raw_len % line_len {
+                    0 => 0,
+                    rest => 3 + rest.div_ceil(3) * 4,
+                };
+                full_lines.checked_add(rest + (UU_BEGIN.len() + UU_END.len()) as u64)
+            }
+        }
+    }
+
+    /// Returns the bytes written between two encoded chunks of a stream, which are separate lines for base64.
+    pub(crate) fn chunk_separator(self) -> &'static [u8] {
+        match self {
+            Encoding::Base64 => b"\r\n",
+            _ => &[],
+        }
+    }
+
+    /// Returns the bytes written before the encoded data, if the encoding frames it.
+    pub(crate) fn prefix(self) -> &'static [u8] {
+        match self {
+            #[cfg(feature = "uuencode")]
+            Encoding::UuEncode => UU_BEGIN,
+            _ => &[],
+        }
+    }
+
+    /// Returns the bytes written after the encoded data, if the encoding frames it.
+    pub(crate) fn suffix(self) -> &'static [u8] {
+        match self {
+            #[cfg(feature = "uuencode")]
+            Encoding::UuEncode => UU_END,
+            _ => &[],
+        }
+    }
+
+    /// Encodes one chunk of a stream, without the framing of [`Encoding::prefix`] and [`Encoding::suffix`].
+    ///
+    /// Chunks must be split on multiples of 3 bytes for base64, and of 45 bytes for uuencoding.
+    pub(crate) fn encode_chunk(self, input: &mut Vec<u8>) {
+        match self {
+            #[cfg(feature = "uuencode")]
+            Encoding::UuEncode => *input = uuencode_lines(input),
+            _ => self.encode(input),
+        }
+    }
+
+    pub fn encode(self, input: &mut Vec<u8>) {
+        match self {
+            Encoding::Base64 => *input = base64_lines(input),
+            Encoding::Base64UrlSafe => {
+                *input = general_purpose::URL_SAFE_NO_PAD.encode(&input).into_bytes()
+            }
+            Encoding::Base64UrlSafePadded => {
+                *input = general_purpose::URL_SAFE.encode(&input).into_bytes()
             }
             Encoding::QuotedPrintable => *input = quoted_printable::encode(&input),
             Encoding::SevenBit | Encoding::EightBit => (),
+            #[cfg(feature = "uuencode")]
+            Encoding::UuEncode => {
+                let mut encoded = UU_BEGIN.to_vec();
+                encoded.extend(uuencode_lines(input));
+                encoded.extend_from_slice(UU_END);
+                *input = encoded;
+            }
+        }
+    }
+
+    /// Decodes the input in place.
+    ///
+    /// Base64 input is accepted with or without padding, and with line breaks or other whitespace,
+    /// such as the lines of at most 76 characters written by MIME clients. Every base64 variant
+    /// accepts both the standard and the URL-safe alphabet, since `Content-Transfer-Encoding: base64`
+    /// doesn't say which one was used.
+    /// Returns an `InvalidData` error if the input is not validly encoded.
+    pub fn decode(self, input: &mut Vec<u8>) -> io::Result<()> {
+        let decoded = match self {
+            Encoding::Base64 | Encoding::Base64UrlSafe | Encoding::Base64UrlSafePadded => {
+                input.retain(|byte| !byte.is_ascii_whitespace());
+                for byte in input.iter_mut() {
+                    match byte {
+                        b'-' => *byte = b'+',
+                        b'_' => *byte = b'/',
+                        _ => {}
+                    }
+                }
+                STANDARD_DECODE.decode(&input).map_err(invalid_data)
+            }
+            Encoding::QuotedPrintable => {
+                quoted_printable::decode(&input, quoted_printable::ParseMode::Robust)
+                    .map_err(invalid_data)
+            }
+            Encoding::SevenBit | Encoding::EightBit => return Ok(()),
+            #[cfg(feature = "uuencode")]
+            Encoding::UuEncode => uudecode(input),
+        };
+        *input = decoded?;
+        Ok(())
+    }
+}
+
+/// Encodes a whole buffer at once, including any framing such as uuencoding's `begin` and `end` lines.
+///
+/// The output is the same as the body of a part streamed with the encoding, and its length
+/// matches [`Encoding::encoded_len`]. [`Encoding::Base64`] output is padded and wrapped in lines
+/// of 76 characters, without a line break after the last one.
+///
+/// ```
+/// use http_client_multipart::{encode_all, Encoding};
+///
+/// assert_eq!(encode_all(b"hello", Encoding::Base64), b"aGVsbG8=");
+/// ```
+pub fn encode_all(data: &[u8], encoding: Encoding) -> Vec<u8> {
+    let mut encoded = data.to_vec();
+    encoding.encode(&mut encoded);
+    encoded
+}
+
+/// Encodes a chunk of a quoted-printable stream, only encoding whole lines.
+///
+/// Each chunk is appended to `line`, which holds the unfinished last line of the data so far.
+/// Complete lines are encoded, so trailing whitespace is encoded as `=20`/`=09` only at real
+/// line ends, and encoded lines stay within 76 characters across chunks. A line longer than
+/// `max_line` is flushed early with a soft line break, keeping a trailing `\r` for the next chunk.
+pub(crate) fn encode_qp_chunk(line: &mut Vec<u8>, chunk: &[u8], max_line: usize) -> Vec<u8> {
+    line.extend_from_slice(chunk);
+    let complete = line
+        .windows(2)
+        .rposition(|pair| pair == b"\r\n")
+        .map_or(0, |i| i + 2);
+    let mut output = quoted_printable::encode(&line[..complete]);
+    line.drain(..complete);
+
+    let flush = if line.ends_with(b"\r") {
+        line.len() - 1
+    } else {
+        line.len()
+    };
+    if line.len() > max_line && flush > 0 {
+        // One character less, to leave room for the `=` of the soft line break
+        let options = quoted_printable::Options::default().line_length_limit(75);
+        output.extend(quoted_printable::encode_with_options(&line[..flush], options).into_bytes());
+        output.extend_from_slice(b"=\r\n");
+        line.drain(..flush);
+    }
+    output
+}
+
+fn invalid_data(e: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Encodes data as uuencoded lines of up to 45 bytes each, ending in CRLF.
+#[cfg(feature = "uuencode")]
+fn uuencode_lines(input: &[u8]) -> Vec<u8> {
+    let uu_char = |value: u8| if value == 0 { b'`' } else { value + 32 };
+    let mut output = Vec::with_capacity(input.len() / 3 * 4 + input.len() / UU_LINE_LEN * 3 + 7);
+    for line in input.chunks(UU_LINE_LEN) {
+        output.push(uu_char(line.len() as u8));
+        for group in line.chunks(3) {
+            let mut bytes = [0; 3];
+            bytes[..group.len()].copy_from_slice(group);
+            output.push(uu_char(bytes[0] >> 2));
+            output.push(uu_char((bytes[0] << 4 | bytes[1] >> 4) & 0x3f));
+            output.push(uu_char((bytes[1] << 2 | bytes[2] >> 6) & 0x3f));
+            output.push(uu_char(bytes[2] & 0x3f));
+        }
+        output.extend_from_slice(b"\r\n");
+    }
+    output
+}
+
+/// Encodes base64 in lines of [`BASE64_LINE_LEN`] raw bytes separated by CRLF.
+///
+/// Every line but the last encodes a multiple of 3 bytes, so only the last one can be padded.
+fn base64_lines(input: &[u8]) -> Vec<u8> {
+    let mut output =
+        Vec::with_capacity(input.len().div_ceil(3) * 4 + input.len() / BASE64_LINE_LEN * 2);
+    for (i, line) in input.chunks(BASE64_LINE_LEN).enumerate() {
+        if i > 0 {
+            output.extend_from_slice(b"\r\n");
+        }
+        output.extend(general_purpose::STANDARD.encode(line).into_bytes());
+    }
+    output
+}
+
+/// Decodes uuencoded data, with or without the `begin` and `end` lines.
+#[cfg(feature = "uuencode")]
+fn uudecode(input: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid uuencoded data");
+    let uu_value = |c: u8| match c {
+        b' '..=b'`' => Ok((c - b' ') & 0x3f),
+        _ => Err(invalid()),
+    };
+    let mut output = Vec::with_capacity(input.len() / 4 * 3);
+    for line in input.split(|b| *b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.starts_with(b"begin ") {
+            continue;
+        }
+        if line == b"end" {
+            break;
         }
+        let Some((&len, data)) = line.split_first() else {
+            continue;
+        };
+        let len = uu_value(len)? as usize;
+        if data.len() < len.div_ceil(3) * 4 {
+            return Err(invalid());
+        }
+        let mut decoded = Vec::with_capacity(len + 2);
+        for group in data.chunks_exact(4).take(len.div_ceil(3)) {
+            let [a, b, c, d] = [group[0], group[1], group[2], group[3]].map(uu_value);
+            let (a, b, c, d) = (a?, b?, c?, d?);
+            decoded.extend([a << 2 | b >> 4, b << 4 | c >> 2, c << 6 | d]);
+        }
+        decoded.truncate(len);
+        output.extend(decoded);
+    }
+    Ok(output)
+}
+
+impl FromStr for Encoding {
+    type Err = io::Error;
+
+    /// Parses a `Content-Transfer-Encoding` value, ignoring case.
+    ///
+    /// `base64` always parses to [`Encoding::Base64`], since the header does not carry the alphabet.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "7bit" => Ok(Encoding::SevenBit),
+            "8bit" => Ok(Encoding::EightBit),
+            "base64" => Ok(Encoding::Base64),
+            "quoted-printable" => Ok(Encoding::QuotedPrintable),
+            #[cfg(feature = "uuencode")]
+            "x-uuencode" | "x-uue" | "uuencode" => Ok(Encoding::UuEncode),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported Content-Transfer-Encoding `{}`", other),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_url_safe_alphabet() {
+        let data = [0xfb, 0xff, 0xbf, 0xfe];
+
+        let mut standard = data.to_vec();
+        Encoding::Base64.encode(&mut standard);
+        assert_eq!(standard, b"+/+//g==");
+
+        let mut url_safe = data.to_vec();
+        Encoding::Base64UrlSafe.encode(&mut url_safe);
+        assert_eq!(url_safe, b"-_-__g");
+        assert!(!url_safe.iter().any(|b| *b == b'+' || *b == b'/'));
+
+        let mut padded = data.to_vec();
+        Encoding::Base64UrlSafePadded.encode(&mut padded);
+        assert_eq!(padded, b"-_-__g==");
+
+        for (encoding, mut encoded) in [
+            (Encoding::Base64UrlSafe, url_safe),
+            (Encoding::Base64UrlSafePadded, padded),
+        ] {
+            encoding.decode(&mut encoded).unwrap();
+            assert_eq!(encoded, data);
+            assert_eq!(encoding.to_str(), "base64");
+        }
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        for len in 0..=10 {
+            let data = vec![0xa5; len];
+            for encoding in [
+                Encoding::Base64,
+                Encoding::Base64UrlSafe,
+                Encoding::Base64UrlSafePadded,
+                Encoding::SevenBit,
+            ] {
+                let mut encoded = data.clone();
+                encoding.encode(&mut encoded);
+                assert_eq!(encoding.encoded_len(len), Some(encoded.len()));
+            }
+        }
+        assert_eq!(Encoding::QuotedPrintable.encoded_len(10), None);
+    }
+
+    #[cfg(feature = "uuencode")]
+    #[test]
+    fn test_uuencode_round_trip() {
+        let data = (0..=255).cycle().take(100).collect::<Vec<u8>>();
+        let mut encoded = data.clone();
+        Encoding::UuEncode.encode(&mut encoded);
+        assert!(encoded.starts_with(b"begin 644 file\r\n"));
+        assert!(encoded.ends_with(b"`\r\nend\r\n"));
+        assert!(encoded
+            .split(|b| *b == b'\n')
+            .all(|line| line.is_empty() || line.ends_with(b"\r")));
+        assert_eq!(
+            Encoding::UuEncode.encoded_len(data.len()),
+            Some(encoded.len())
+        );
+
+        let mut cat = b"Cat".to_vec();
+        Encoding::UuEncode.encode_chunk(&mut cat);
+        assert_eq!(cat, b"#0V%T\r\n");
+
+        Encoding::UuEncode.decode(&mut encoded).unwrap();
+        assert_eq!(encoded, data);
+        assert_eq!(
+            "x-uuencode".parse::<Encoding>().unwrap(),
+            Encoding::UuEncode
+        );
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("BASE64".parse::<Encoding>().unwrap(), Encoding::Base64);
+        assert_eq!(
+            "quoted-printable".parse::<Encoding>().unwrap(),
+            Encoding::QuotedPrintable
+        );
+        assert!("x-unknown".parse::<Encoding>().is_err());
     }
 }
@@ -0,0 +1,70 @@
+use std::{
+    future::Future,
+    io,
+    path::PathBuf,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use async_fs::File;
+use futures_lite::AsyncRead;
+
+type OpenFuture = Pin<Box<dyn Future<Output = io::Result<File>> + Send + Sync>>;
+
+/// A file reader that opens the file on the first read.
+///
+/// Forms that are built but never sent don't hold a file descriptor for each lazy part.
+/// Both opening and reading go through `async_fs`, so neither blocks the executor.
+pub(crate) struct LazyFile {
+    path: PathBuf,
+    state: State,
+}
+
+enum State {
+    Closed,
+    Opening(OpenFuture),
+    Open(File),
+}
+
+impl LazyFile {
+    pub(crate) fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            state: State::Closed,
+        }
+    }
+}
+
+impl AsyncRead for LazyFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            match &mut this.state {
+                State::Closed => {
+                    let path = this.path.clone();
+                    this.state = State::Opening(Box::pin(async move { File::open(path).await }));
+                }
+                State::Opening(open) => {
+                    let file = ready!(open.as_mut().poll(cx)).map_err(|e| {
+                        io::Error::new(
+                            e.kind(),
+                            format!("Failed to open `{}`: {}", this.path.display(), e),
+                        )
+                    });
+                    match file {
+                        Ok(file) => this.state = State::Open(file),
+                        Err(e) => {
+                            this.state = State::Closed;
+                            return Poll::Ready(Err(e));
+                        }
+                    }
+                }
+                State::Open(file) => return Pin::new(file).poll_read(cx, buf),
+            }
+        }
+    }
+}
@@ -50,19 +50,57 @@
 //! ```
 use http_types::Request;
 
+mod batch;
+mod builder;
+#[cfg(any(feature = "crc", feature = "sha256"))]
+mod checksum;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+mod decompress;
+mod disposition;
 mod encoding;
+mod lazy_file;
 mod multipart;
+mod parser;
 mod part;
+mod prefetch_stream;
 mod reader_stream;
-
-pub use encoding::Encoding;
-pub use multipart::Multipart;
+mod sequence_reader;
+mod spool;
+#[cfg(feature = "surf")]
+mod surf_ext;
+#[cfg(feature = "testing")]
+mod testing;
+#[cfg(feature = "timeout")]
+mod timeout;
+#[cfg(feature = "tokio")]
+mod tokio_ext;
+
+pub use builder::MultipartBuilder;
+#[cfg(feature = "crc")]
+pub use checksum::Crc32Handle;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub use decompress::Codec;
+pub use disposition::ContentDisposition;
+pub use encoding::{encode_all, Encoding};
+pub use mime_guess::Mime;
+pub use multipart::{
+    ChunkInfo, ChunkKind, DedupKeep, FileInfo, Multipart, PartRange, TransferProfile,
+};
+#[cfg(feature = "surf")]
+pub use surf_ext::RequestBuilderMultipartExt;
+#[cfg(feature = "testing")]
+pub use testing::ExpectedPart;
 
 pub type StreamChunk = std::result::Result<Vec<u8>, futures_lite::io::Error>;
 
 /// Generates a random boundary string.
 fn generate_boundary() -> String {
-    (0..30).map(|_| fastrand::alphanumeric()).collect()
+    random_alphanumeric(30)
+}
+
+/// Generates a random alphanumeric string of the given length.
+fn random_alphanumeric(len: usize) -> String {
+    (0..len).map(|_| fastrand::alphanumeric()).collect()
 }
 
 // Extension trait for adding multipart functionality.
@@ -1,18 +1,139 @@
-use crate::{generate_boundary, part::Part, Encoding, StreamChunk};
-use futures_lite::{AsyncBufRead, AsyncReadExt, Stream, StreamExt};
-use http_types::{Body, Request, Result};
+use crate::{
+    generate_boundary, parser,
+    part::Part,
+    prefetch_stream::PrefetchStream,
+    random_alphanumeric,
+    reader_stream::{ReaderStream, CHUNK_SIZE},
+    sequence_reader::{Segment, SequenceReader},
+    spool::{spool, SPOOL_MEMORY_LIMIT},
+    ContentDisposition, Encoding, StreamChunk,
+};
+use futures_lite::{
+    AsyncBufRead, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, Stream, StreamExt,
+};
+use http_types::{format_err, headers::Headers, Body, Method, Request, Response, Result, Url};
+use mime_guess::Mime;
 use std::{
     borrow::Cow,
-    io::{Read, Seek},
-    path::Path,
+    collections::HashMap,
+    io::{self, Read, Seek},
+    ops::Range,
+    path::{Path, PathBuf},
     pin::Pin,
+    sync::{Arc, Mutex},
 };
 
+/// Metadata about a file added to a form, as returned by [`Multipart::add_file_info`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileInfo {
+    /// The filename sent in the `Content-Disposition` header.
+    pub filename: String,
+    /// The content type guessed from the file extension.
+    pub mime: Mime,
+    /// The size of the file on disk, before any encoding.
+    pub len: u64,
+    /// The transfer encoding applied to the part.
+    pub encoding: Option<Encoding>,
+}
+
+/// The byte offsets of a part within the serialized form, as returned by [`Multipart::byte_ranges`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartRange {
+    /// The range of the part's header block, including the blank line after it.
+    pub header: Range<usize>,
+    /// The range of the part's encoded body.
+    pub body: Range<usize>,
+}
+
+/// Which field to keep among fields sharing a name, see [`Multipart::deduplicate_fields`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupKeep {
+    /// Keep the first field with each name.
+    First,
+    /// Keep the last field with each name, so later values override earlier ones.
+    Last,
+}
+
+/// The transport a form is sent over, which decides how parts are transfer-encoded, see [`Multipart::with_profile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransferProfile {
+    /// HTTP carries binary data as-is: parts are sent without a transfer encoding.
+    #[default]
+    Http,
+    /// Email restricted to 7bit: text parts are quoted-printable and binary parts base64.
+    Email7bit,
+    /// Email allowing 8bit data: text parts are sent as 8bit and binary parts base64,
+    /// since 8bit still limits line lengths and forbids NUL bytes.
+    Email8bit,
+}
+
+impl TransferProfile {
+    /// Returns the encoding for a part of this profile, text parts being those with a textual content type.
+    fn encoding_for(self, is_text: bool) -> Option<Encoding> {
+        match (self, is_text) {
+            (TransferProfile::Http, _) => None,
+            (TransferProfile::Email7bit, true) => Some(Encoding::QuotedPrintable),
+            (TransferProfile::Email8bit, true) => Some(Encoding::EightBit),
+            (_, false) => Some(Encoding::Base64),
+        }
+    }
+}
+
+/// Metadata about a chunk emitted by [`Multipart::into_stream_debug`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkInfo {
+    /// The offset of the chunk in the serialized form.
+    pub offset: u64,
+    /// The length of the chunk.
+    pub len: usize,
+    /// What the chunk contains.
+    pub kind: ChunkKind,
+    /// The field name of the part the chunk belongs to, if any.
+    pub field_name: Option<String>,
+}
+
+/// The contents of a chunk described by a [`ChunkInfo`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkKind {
+    /// A boundary line, before, between or after the parts.
+    Boundary,
+    /// The header block of a part, including the blank line after it.
+    Header,
+    /// Part of the encoded body of a part.
+    Body,
+}
+
+/// A boundary line of the serialized form, see `Multipart::boundary_delimiter`.
+#[derive(Debug, Clone, Copy)]
+enum Delimiter {
+    /// Before the first part.
+    First,
+    /// Between two parts.
+    Middle,
+    /// After the last part.
+    Last,
+}
+
+/// A segment of [`Multipart::into_stream_debug`], with each chunk tagged by kind and field name.
+type TaggedSegment = Pin<Box<dyn Stream<Item = (ChunkKind, Option<String>, StreamChunk)>>>;
+
 /// A struct representing a multipart form.
 #[derive(Debug)]
 pub struct Multipart<'m> {
-    boundary: String,
+    pub(crate) boundary: String,
+    pub(crate) subtype: String,
+    params: Vec<(String, String)>,
     fields: Vec<Part<'m>>,
+    default_file_encoding: Option<Encoding>,
+    base_dir: Option<PathBuf>,
+    pub(crate) default_filename: String,
+    max_text_len: Option<usize>,
+    normalize_text_crlf: bool,
+    profile: TransferProfile,
+    buf_size: Option<usize>,
+    total_buffer_budget: Option<usize>,
+    preamble: Option<String>,
+    epilogue: Option<String>,
 }
 
 impl Default for Multipart<'_> {
@@ -26,23 +147,569 @@ impl<'m> Multipart<'m> {
     pub fn new() -> Self {
         Self {
             boundary: generate_boundary(),
+            subtype: "form-data".into(),
+            params: Vec::new(),
             fields: Vec::new(),
+            default_file_encoding: None,
+            base_dir: None,
+            default_filename: "file".into(),
+            max_text_len: None,
+            normalize_text_crlf: false,
+            profile: TransferProfile::Http,
+            buf_size: None,
+            total_buffer_budget: None,
+            preamble: None,
+            epilogue: None,
+        }
+    }
+
+    /// Creates a new `multipart/mixed` form with a randomly generated boundary.
+    ///
+    /// Unlike `multipart/form-data`, parts of a mixed form may omit the field name,
+    /// see [`Multipart::add_attachment`].
+    pub fn mixed() -> Self {
+        Self {
+            subtype: "mixed".into(),
+            ..Self::new()
+        }
+    }
+
+    /// Creates a new `multipart/related` form (RFC 2387) with a randomly generated boundary.
+    ///
+    /// `root_type` is the content type of the root part, sent as the `type` parameter.
+    /// `start` is the `Content-ID` of the root part, when it isn't the first one, and `start_info`
+    /// is extra information about it: for SOAP MTOM/XOP, `application/xop+xml` with a
+    /// `start_info` of `application/soap+xml`.
+    pub fn related(root_type: &str, start: Option<&str>, start_info: Option<&str>) -> Self {
+        let mut params = vec![("type".to_owned(), root_type.to_owned())];
+        if let Some(start) = start {
+            params.push(("start".to_owned(), start.to_owned()));
+        }
+        if let Some(start_info) = start_info {
+            params.push(("start-info".to_owned(), start_info.to_owned()));
+        }
+        Self {
+            subtype: "related".into(),
+            params,
+            ..Self::new()
+        }
+    }
+
+    /// Creates a new `multipart/form-data` form with a text field for each `(name, value)` pair.
+    ///
+    /// The form has no [`Multipart::with_max_text_len`] cap yet, so values of any length are accepted.
+    pub fn with_fields<N, V>(pairs: Vec<(N, V)>) -> Self
+    where
+        N: Into<Cow<'m, str>>,
+        V: AsRef<str>,
+    {
+        let mut multipart = Self::new();
+        for (name, value) in pairs {
+            multipart.add_text(name, value);
+        }
+        multipart
+    }
+
+    /// Sets the encoding applied to file parts added afterwards.
+    ///
+    /// Text fields are not affected. Passing `Some(encoding)` to a file-adding method overrides the default
+    /// for that part; use `Some(Encoding::EightBit)` to opt a single file out of the default.
+    pub fn with_default_file_encoding(mut self, encoding: Encoding) -> Self {
+        self.default_file_encoding = Some(encoding);
+        self
+    }
+
+    /// Picks the transfer encoding of parts added afterwards for the transport the form is sent over.
+    ///
+    /// Only parts added without an encoding are affected: an explicit encoding, or the default file
+    /// encoding, takes precedence. Parts added with an exact `Content-Disposition` or already serialized are left as they are.
+    pub fn with_profile(mut self, profile: TransferProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Resolves the paths passed to [`Multipart::add_file`] against a base directory.
+    ///
+    /// Relative paths are joined onto `dir`, and any path that resolves outside of it (e.g. `../secret`),
+    /// after following symlinks, is rejected.
+    pub fn with_base_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.base_dir = Some(dir.into());
+        self
+    }
+
+    /// Replaces the boundary, e.g. to match one agreed on with the server.
+    ///
+    /// Returns an error if the boundary is empty, longer than the 70 characters allowed by RFC 2046,
+    /// or contains characters not allowed in a boundary.
+    pub fn with_boundary(mut self, boundary: &str) -> Result<Self> {
+        if boundary.is_empty() || boundary.len() > 70 {
+            return Err(format_err!(
+                "Boundary must be between 1 and 70 characters, found {}",
+                boundary.len()
+            ));
+        }
+        if let Some(c) = boundary.chars().find(|c| !is_boundary_char(*c)) {
+            return Err(format_err!("Invalid character {:?} in boundary", c));
+        }
+        self.boundary = boundary.to_owned();
+        Ok(self)
+    }
+
+    /// Replaces the boundary with a recognizable prefix followed by random characters,
+    /// like browsers do with `----WebKitFormBoundary...`.
+    ///
+    /// The random part is shortened so the boundary stays within the 70 characters allowed by RFC 2046,
+    /// but always keeps at least 16 characters. Returns an error if the prefix is too long or contains
+    /// characters not allowed in a boundary.
+    pub fn with_boundary_prefix(mut self, prefix: &str) -> Result<Self> {
+        const MAX_BOUNDARY_LEN: usize = 70;
+        const MIN_RANDOM_LEN: usize = 16;
+        if let Some(c) = prefix.chars().find(|c| !is_boundary_char(*c)) {
+            return Err(format_err!("Invalid character {:?} in boundary prefix", c));
+        }
+        if prefix.len() > MAX_BOUNDARY_LEN - MIN_RANDOM_LEN {
+            return Err(format_err!(
+                "Boundary prefix is longer than {} characters",
+                MAX_BOUNDARY_LEN - MIN_RANDOM_LEN
+            ));
+        }
+        let random_len = (MAX_BOUNDARY_LEN - prefix.len()).min(30);
+        self.boundary = format!("{}{}", prefix, random_alphanumeric(random_len));
+        Ok(self)
+    }
+
+    /// Sets the filename sent for paths without a file name component, such as `..`.
+    ///
+    /// Also used by [`Multipart::sanitize_filenames`] for filenames that have nothing left.
+    /// Defaults to `file`.
+    pub fn with_default_filename(mut self, filename: impl Into<String>) -> Self {
+        self.default_filename = filename.into();
+        self
+    }
+
+    /// Caps the size of text fields at `max_len` bytes, to catch large values that should be sent as files.
+    ///
+    /// Every text method checks the fields added after the cap is set. The fallible ones
+    /// ([`Multipart::try_add_text`], [`Multipart::insert_text`], [`Multipart::add_text_mime`] and
+    /// [`Multipart::add_text_typed`]) return an error for longer values, while [`Multipart::add_text`],
+    /// [`Multipart::add_text_opt`], [`Multipart::add_enc_text`] and [`Multipart::prepend_text`] can't
+    /// report errors and panic instead.
+    /// Binary values, such as those of [`Multipart::add_binary_field`] and [`Multipart::add_base64`],
+    /// aren't text and are never checked. Unlimited by default.
+    pub fn with_max_text_len(mut self, max_len: usize) -> Self {
+        self.max_text_len = Some(max_len);
+        self
+    }
+
+    /// Normalizes line endings in text fields added afterwards to CRLF.
+    ///
+    /// Lone `\n` and `\r` are replaced with `\r\n`, as HTML forms do, for servers that
+    /// expect normalized values. File parts, and fields whose content type isn't textual (such as
+    /// those added with [`Multipart::add_binary_field`]), are left untouched.
+    pub fn with_normalize_text_crlf(mut self) -> Self {
+        self.normalize_text_crlf = true;
+        self
+    }
+
+    /// Sets the chunk size used to read parts when the form is set as a request or response body.
+    ///
+    /// Without it, the chunk size is picked from each part's length.
+    pub fn with_buf_size(mut self, buf_size: usize) -> Self {
+        self.buf_size = Some(buf_size);
+        self
+    }
+
+    /// Caps the memory used by the read buffers of all parts together when the form is streamed or read.
+    ///
+    /// The budget is divided evenly among the reader-backed parts, and no part is read in chunks larger
+    /// than its share, whatever `buf_size` is given. Base64 and uuencoded parts round their share up to
+    /// a whole number of encoded groups, so the total can exceed the budget by a few bytes per part.
+    pub fn with_total_buffer_budget(mut self, bytes: usize) -> Self {
+        self.total_buffer_budget = Some(bytes);
+        self
+    }
+
+    /// Returns the chunk size each part is read with, within the share of the total buffer budget if one is set.
+    fn part_buf_sizes(&self, buf_size: Option<usize>) -> Vec<Option<usize>> {
+        let share = self.total_buffer_budget.map(|budget| {
+            let readers = self.fields.iter().filter(|f| f.is_reader_backed()).count();
+            (budget / readers.max(1)).max(1)
+        });
+        self.fields
+            .iter()
+            .map(|field| {
+                let size = field.buf_size(buf_size);
+                match share {
+                    Some(share) => Some(size.unwrap_or(CHUNK_SIZE).min(share)),
+                    None => size,
+                }
+            })
+            .collect()
+    }
+
+    /// Sets a preamble, written before the first boundary.
+    ///
+    /// RFC 2046 readers ignore it, it is meant for readers that don't understand MIME, e.g.
+    /// `This is a multi-part message in MIME format.` Nothing is written for a form without parts.
+    pub fn with_preamble(mut self, text: impl Into<String>) -> Self {
+        self.preamble = Some(text.into());
+        self
+    }
+
+    /// Sets an epilogue, written after the closing boundary.
+    ///
+    /// Like the preamble, RFC 2046 readers ignore it. Nothing is written for a form without parts.
+    pub fn with_epilogue(mut self, text: impl Into<String>) -> Self {
+        self.epilogue = Some(text.into());
+        self
+    }
+
+    /// Sets the subtype of the top-level `Content-Type`, e.g. `mixed` or `related` for `multipart/mixed`.
+    ///
+    /// The parts are left as they are. Returns an error if the subtype is not a valid token.
+    pub fn with_subtype(mut self, subtype: &str) -> Result<Self> {
+        if subtype.is_empty() || !subtype.bytes().all(is_token_char) {
+            return Err(format_err!("Invalid multipart subtype `{}`", subtype));
+        }
+        self.subtype = subtype.to_ascii_lowercase();
+        Ok(self)
+    }
+
+    /// Adds a parameter to the top-level `Content-Type` of the form, such as `type` for `multipart/related`.
+    ///
+    /// Returns an error for `boundary`, which is managed by the form, for `charset`,
+    /// which multipart types must not carry (set it on individual parts instead),
+    /// and for names that are not valid tokens.
+    pub fn with_content_type_param(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<Self> {
+        let name = name.into();
+        if name.is_empty() || !name.bytes().all(is_token_char) {
+            return Err(format_err!(
+                "Invalid content type parameter name `{}`",
+                name
+            ));
+        }
+        if name.eq_ignore_ascii_case("boundary") {
+            return Err(format_err!(
+                "The boundary parameter is managed by the form, set the boundary instead"
+            ));
+        }
+        if name.eq_ignore_ascii_case("charset") {
+            return Err(format_err!(
+                "multipart/{} must not have a charset parameter, set it on the parts instead",
+                self.subtype
+            ));
+        }
+        self.params.push((name, value.into()));
+        Ok(self)
+    }
+
+    /// Returns the `Content-Type` header value for this form, including the boundary.
+    pub fn content_type(&self) -> String {
+        let mut content_type = format!(
+            "multipart/{}; boundary={}",
+            self.subtype,
+            quote_param(&self.boundary)
+        );
+        for (name, value) in &self.params {
+            content_type.push_str(&format!("; {}={}", name, quote_param(value)));
+        }
+        content_type
+    }
+
+    /// Creates an empty form reusing the subtype and boundary of a `Content-Type` header value,
+    /// such as `multipart/form-data; boundary="simple boundary"`.
+    ///
+    /// Quoted and unquoted boundaries are accepted. Returns an error if the value isn't a multipart
+    /// type, or the boundary is missing or not a valid RFC 2046 boundary.
+    pub fn from_content_type(header_value: &str) -> Result<Multipart<'static>> {
+        let (subtype, boundary) = parser::parse_content_type(header_value)?;
+        check_received_boundary(&boundary)?;
+        Ok(Multipart::from_parsed(boundary, subtype, Vec::new()))
+    }
+
+    /// Parses the multipart body of a request back into a `Multipart` form.
+    ///
+    /// The boundary is read from the request's `Content-Type` header and reused for the form.
+    /// Returns an error if it isn't a valid RFC 2046 boundary, like [`Multipart::from_content_type`].
+    /// The request body is consumed.
+    pub async fn from_request(req: &mut Request) -> Result<Multipart<'static>> {
+        let content_type = req
+            .header("Content-Type")
+            .ok_or_else(|| format_err!("Request has no Content-Type header"))?;
+        let (subtype, boundary) = parser::parse_content_type(content_type.last().as_str())?;
+        check_received_boundary(&boundary)?;
+        let body = req.take_body().into_bytes().await?;
+        let fields = parser::parse(&body, &boundary)?;
+        Ok(Multipart::from_parsed(boundary, subtype, fields))
+    }
+
+    /// Parses a multipart body with the given boundary, enforcing limits for untrusted input.
+    ///
+    /// Returns an error if the body has more than `max_parts` parts, or if any part body
+    /// is larger than `max_part_size` bytes. The form is assumed to be `multipart/form-data`.
+    pub fn from_bytes_limited(
+        bytes: &[u8],
+        boundary: &str,
+        max_parts: usize,
+        max_part_size: usize,
+    ) -> Result<Multipart<'static>> {
+        let fields = parser::parse_limited(bytes, boundary, max_parts, max_part_size)?;
+        Ok(Multipart::from_parsed(
+            boundary.to_owned(),
+            "form-data".into(),
+            fields,
+        ))
+    }
+
+    fn from_parsed(
+        boundary: String,
+        subtype: String,
+        fields: Vec<Part<'static>>,
+    ) -> Multipart<'static> {
+        Multipart {
+            boundary,
+            subtype,
+            fields,
+            ..Multipart::new()
         }
     }
 
     /// Adds a text field to the form.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is longer than the maximum set by [`Multipart::with_max_text_len`],
+    /// use [`Multipart::try_add_text`] to get an error instead.
     pub fn add_text(&mut self, name: impl Into<Cow<'m, str>>, value: impl AsRef<str>) {
-        self.fields.push(Part::text(name, value.as_ref(), None));
+        self.assert_text_len(value.as_ref().len());
+        let part = self.text_part(name, value.as_ref(), None);
+        self.push_part(part);
+    }
+
+    /// Adds a part to the end of the form, applying the transfer profile.
+    fn push_part(&mut self, part: Part<'m>) {
+        let part = self.profiled(part);
+        self.fields.push(part);
+    }
+
+    /// Picks the transfer encoding of a part without one from the profile set by [`Multipart::with_profile`].
+    fn profiled(&self, mut part: Part<'m>) -> Part<'m> {
+        if part.encoding().is_none() {
+            let is_text = is_text_type(part.decoded_content_type());
+            part.set_encoding(self.profile.encoding_for(is_text));
+        }
+        part
+    }
+
+    /// Creates a text part, normalizing line endings if [`Multipart::with_normalize_text_crlf`] is set.
+    fn text_part(
+        &self,
+        name: impl Into<Cow<'m, str>>,
+        value: impl AsRef<[u8]>,
+        encoding: Option<Encoding>,
+    ) -> Part<'m> {
+        if self.normalize_text_crlf {
+            Part::text(name, normalize_crlf(value.as_ref()), encoding)
+        } else {
+            Part::text(name, value, encoding)
+        }
+    }
+
+    /// Adds a text field to the form, checking it against [`Multipart::with_max_text_len`].
+    ///
+    /// Returns an error if the value is longer than the maximum text length.
+    pub fn try_add_text(
+        &mut self,
+        name: impl Into<Cow<'m, str>>,
+        value: impl AsRef<str>,
+    ) -> Result<()> {
+        self.check_text_len(value.as_ref().len())?;
+        let part = self.text_part(name, value.as_ref(), None);
+        self.push_part(part);
+        Ok(())
+    }
+
+    /// Checks a text value length against the maximum set by [`Multipart::with_max_text_len`].
+    fn check_text_len(&self, len: usize) -> Result<()> {
+        match self.max_text_len {
+            Some(max_len) if len > max_len => Err(format_err!(
+                "Text value of {} bytes exceeds the maximum of {} bytes, consider sending it as a file",
+                len,
+                max_len
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Panics if a text value is longer than the maximum, for the methods that can't return an error.
+    fn assert_text_len(&self, len: usize) {
+        if let Err(e) = self.check_text_len(len) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Adds a text field before all the other parts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is longer than the maximum set by [`Multipart::with_max_text_len`],
+    /// use [`Multipart::insert_text`] at index 0 to get an error instead.
+    pub fn prepend_text(&mut self, name: impl Into<Cow<'m, str>>, value: impl AsRef<str>) {
+        self.assert_text_len(value.as_ref().len());
+        let part = self.text_part(name, value.as_ref(), None);
+        self.fields.insert(0, self.profiled(part));
+    }
+
+    /// Adds a text field at `index`, shifting the parts after it.
+    ///
+    /// Returns an error if `index` is greater than the number of parts.
+    pub fn insert_text(
+        &mut self,
+        index: usize,
+        name: impl Into<Cow<'m, str>>,
+        value: impl AsRef<str>,
+    ) -> Result<()> {
+        self.check_index(index, self.fields.len() + 1)?;
+        self.check_text_len(value.as_ref().len())?;
+        let part = self.text_part(name, value.as_ref(), None);
+        self.fields.insert(index, self.profiled(part));
+        Ok(())
+    }
+
+    /// Moves the part at `from` to `to`, shifting the parts in between.
+    ///
+    /// Combined with any `add_*` method, e.g. `move_part(m.len() - 1, 0)`, this places
+    /// a part of any kind at a specific position. Returns an error if either index is out of bounds.
+    pub fn move_part(&mut self, from: usize, to: usize) -> Result<()> {
+        self.check_index(from, self.fields.len())?;
+        self.check_index(to, self.fields.len())?;
+        let part = self.fields.remove(from);
+        self.fields.insert(to, part);
+        Ok(())
+    }
+
+    /// Returns the number of parts in the form.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns whether the form has no parts.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Returns the number of parts, counting the parts of nested multipart bodies as well,
+    /// for checking a form against a server's limit on the total part count.
+    ///
+    /// A nested body, such as a `multipart/mixed` part holding several files, counts as one part
+    /// plus its own parts, recursively. Only in-memory parts are looked into: reader-backed parts,
+    /// and bodies that don't parse with the boundary of their content type, count as a single part.
+    pub fn total_parts(&self) -> usize {
+        count_parts(&self.fields)
+    }
+
+    /// Adds a header, such as `Content-ID` or an `X-*` header, to the part at `index`.
+    ///
+    /// Custom headers are written after `Content-Type` and `Content-Transfer-Encoding`, in the order they are added.
+    /// Combined with any `add_*` method, e.g. `add_part_header(m.len() - 1, "Content-ID", "<logo>")`, this sets
+    /// headers on a part of any kind. Returns an error if `index` is out of bounds, if the name is not a valid token
+    /// or is one of the headers the form writes itself, if the value contains a line break, or if the part
+    /// was added already serialized.
+    pub fn add_part_header(&mut self, index: usize, name: &str, value: &str) -> Result<()> {
+        self.check_index(index, self.fields.len())?;
+        if name.is_empty() || !name.bytes().all(is_token_char) {
+            return Err(format_err!("Invalid header name `{}`", name));
+        }
+        let reserved = [
+            "Content-Disposition",
+            "Content-Type",
+            "Content-Transfer-Encoding",
+        ];
+        if reserved
+            .iter()
+            .any(|header| header.eq_ignore_ascii_case(name))
+        {
+            return Err(format_err!(
+                "The {} header is written by the form and can't be added",
+                name
+            ));
+        }
+        if value.contains(['\r', '\n']) {
+            return Err(format_err!(
+                "Value of header {} contains a line break",
+                name
+            ));
+        }
+        let part = &mut self.fields[index];
+        if part.header_len() == 0 {
+            return Err(format_err!(
+                "Part {} is already serialized, its headers can't be changed",
+                index
+            ));
+        }
+        part.add_header(name.to_owned(), value.to_owned());
+        Ok(())
+    }
+
+    /// Checks that `index` is below `bound`.
+    fn check_index(&self, index: usize, bound: usize) -> Result<()> {
+        if index >= bound {
+            return Err(format_err!(
+                "Part index {} is out of bounds for a form with {} parts",
+                index,
+                self.fields.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Adds a text field to the form only if the value is `Some`.
+    ///
+    /// Returns whether the field was added.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is longer than the maximum set by [`Multipart::with_max_text_len`].
+    pub fn add_text_opt(
+        &mut self,
+        name: impl Into<Cow<'m, str>>,
+        value: Option<impl AsRef<str>>,
+    ) -> bool {
+        match value {
+            Some(value) => {
+                self.add_text(name, value);
+                true
+            }
+            None => false,
+        }
     }
 
+    /// Adds a text field sent with a transfer encoding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is longer than the maximum set by [`Multipart::with_max_text_len`].
     pub fn add_enc_text(
         &mut self,
         name: impl Into<Cow<'m, str>>,
         value: impl AsRef<str>,
         encoding: Encoding,
     ) {
-        self.fields
-            .push(Part::text(name, value.as_ref(), Some(encoding)));
+        self.assert_text_len(value.as_ref().len());
+        let part = self.text_part(name, value.as_ref(), Some(encoding));
+        self.push_part(part);
+    }
+
+    /// Adds a `text/plain` field whose value is sent base64-encoded, with `Content-Transfer-Encoding: base64`.
+    ///
+    /// The value may be any bytes, it is not checked against [`Multipart::with_max_text_len`]
+    /// nor are its line endings normalized.
+    pub fn add_base64(&mut self, name: impl Into<Cow<'m, str>>, value: impl AsRef<[u8]>) {
+        self.push_part(Part::text(name, value, Some(Encoding::Base64)));
     }
 
     /// Adds a text field to the form with a custom mime type.
@@ -54,9 +721,85 @@ impl<'m> Multipart<'m> {
         value: impl AsRef<str>,
         mime: &str,
     ) -> Result<()> {
-        let mut part = Part::text(name, value.as_ref(), None);
-        part.content_type = mime.parse()?;
-        self.fields.push(part);
+        self.add_text_typed(name, value.as_ref(), mime.parse()?, None, None)
+    }
+
+    /// Adds a text field with a content type, an optional charset and an optional transfer encoding.
+    ///
+    /// The charset replaces any `charset` parameter already on `mime`.
+    /// Returns an error if the resulting content type is invalid.
+    pub fn add_text_typed(
+        &mut self,
+        name: impl Into<Cow<'m, str>>,
+        value: impl AsRef<[u8]>,
+        mime: Mime,
+        charset: Option<&str>,
+        encoding: Option<Encoding>,
+    ) -> Result<()> {
+        self.check_text_len(value.as_ref().len())?;
+        let content_type = match charset {
+            Some(charset) => {
+                let mut content_type = mime.essence_str().to_owned();
+                for (param, value) in mime
+                    .params()
+                    .filter(|(param, _)| *param != mime_guess::mime::CHARSET)
+                {
+                    content_type += &format!("; {}={}", param, quote_param(value.as_str()));
+                }
+                content_type += &format!("; charset={}", quote_param(charset));
+                content_type.parse()?
+            }
+            None => mime,
+        };
+        let mut part = if is_text_type(&content_type) {
+            self.text_part(name, value, encoding)
+        } else {
+            Part::text(name, value, encoding)
+        };
+        part.content_type = content_type;
+        self.push_part(part);
+        Ok(())
+    }
+
+    /// Adds a part whose body is the `application/x-www-form-urlencoded` serialization of `pairs`,
+    /// such as `a=1&b=2`, for APIs that accept a nested form.
+    ///
+    /// Keys and values are percent-encoded, with spaces written as `+`.
+    pub fn add_urlencoded<K, V>(
+        &mut self,
+        name: impl Into<Cow<'m, str>>,
+        pairs: impl IntoIterator<Item = (K, V)>,
+    ) where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let body = pairs
+            .into_iter()
+            .map(|(key, value)| {
+                format!("{}={}", urlencode(key.as_ref()), urlencode(value.as_ref()))
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        let mut part = Part::text(name, body, None);
+        part.content_type = "application/x-www-form-urlencoded".parse().unwrap();
+        self.push_part(part);
+    }
+
+    /// Adds a non-file field with an arbitrary content type, such as binary data sent as a value.
+    ///
+    /// Unlike the file methods, no `filename` is sent, so servers treat the part as a plain value.
+    /// The value is sent as is: it isn't checked against [`Multipart::with_max_text_len`], nor are
+    /// its line endings normalized. Returns an error if the mime type is invalid.
+    pub fn add_binary_field(
+        &mut self,
+        name: impl Into<Cow<'m, str>>,
+        value: impl AsRef<[u8]>,
+        content_type: &str,
+        encoding: Option<Encoding>,
+    ) -> Result<()> {
+        let mut part = Part::text(name, value, encoding);
+        part.content_type = content_type.parse()?;
+        self.push_part(part);
         Ok(())
     }
 
@@ -69,198 +812,3121 @@ impl<'m> Multipart<'m> {
         encoding: Option<Encoding>,
     ) -> Result<()> {
         let content_type = mime.parse()?;
+        let encoding = encoding.or(self.default_file_encoding);
         let part = Part::file_raw(name, filename, content_type, encoding, value.as_ref());
-        self.fields.push(part);
+        self.push_part(part);
+        Ok(())
+    }
+
+    /// Adds a file part from a base64 `data:` URI, such as `data:image/png;base64,iVBORw0...`.
+    ///
+    /// The payload is decoded and sent with the media type of the URI, which defaults to
+    /// `text/plain;charset=US-ASCII` when omitted. The file encoding is the form's default.
+    /// Returns an error if the URI is malformed or its payload is not base64.
+    pub fn add_data_uri(
+        &mut self,
+        name: impl Into<Cow<'m, str>>,
+        filename: impl Into<Cow<'m, str>>,
+        data_uri: &str,
+    ) -> Result<()> {
+        let rest = data_uri
+            .get(..5)
+            .filter(|scheme| scheme.eq_ignore_ascii_case("data:"))
+            .map(|_| &data_uri[5..])
+            .ok_or_else(|| format_err!("Data URI must start with `data:`"))?;
+        let (metadata, payload) = rest
+            .split_once(',')
+            .ok_or_else(|| format_err!("Data URI has no `,` before its payload"))?;
+        let media_type = metadata
+            .get(metadata.len().saturating_sub(7)..)
+            .filter(|suffix| suffix.eq_ignore_ascii_case(";base64"))
+            .map(|_| &metadata[..metadata.len() - 7])
+            .ok_or_else(|| format_err!("Only base64 data URIs are supported"))?;
+        let content_type: Mime = match media_type {
+            "" => "text/plain;charset=US-ASCII".parse()?,
+            media_type => media_type
+                .parse()
+                .map_err(|_| format_err!("Invalid media type `{}` in data URI", media_type))?,
+        };
+        let mut data = payload.as_bytes().to_vec();
+        Encoding::Base64.decode(&mut data)?;
+        let part = Part::file_raw(
+            name,
+            filename,
+            content_type,
+            self.default_file_encoding,
+            data,
+        );
+        self.push_part(part);
+        Ok(())
+    }
+
+    /// Adds a file part without a field name, as used for attachments in `multipart/mixed` forms.
+    ///
+    /// Returns an error if the form is `multipart/form-data`, where every part needs a name,
+    /// or if the mime type is invalid.
+    pub fn add_attachment(
+        &mut self,
+        filename: impl Into<Cow<'m, str>>,
+        value: impl AsRef<[u8]>,
+        mime: &str,
+        encoding: Option<Encoding>,
+    ) -> Result<()> {
+        if self.subtype == "form-data" {
+            return Err(format_err!(
+                "Parts without a name are not allowed in multipart/form-data"
+            ));
+        }
+        let encoding = encoding.or(self.default_file_encoding);
+        let part = Part::attachment(filename, mime.parse()?, encoding, value.as_ref());
+        self.push_part(part);
         Ok(())
     }
 
+    /// Appends an already serialized part block to the form.
+    ///
+    /// The bytes must contain the part's headers, the blank line separating them from the body,
+    /// and the body itself, but no boundaries. They are emitted verbatim between the boundaries.
+    ///
+    /// No validation is done: malformed headers, a missing blank line or a body containing
+    /// the boundary will produce a broken form.
+    pub fn append_raw(&mut self, bytes: Vec<u8>) {
+        self.fields.push(Part::pre_serialized(bytes));
+    }
+
     /// Adds a file field to the form from path.
+    ///
+    /// The file is opened right away and read as the form is sent. Dropping the form without
+    /// sending it closes the file immediately.
     pub async fn add_file(
         &mut self,
         name: impl Into<Cow<'m, str>>,
         path: impl AsRef<Path>,
         encoding: Option<Encoding>,
     ) -> Result<()> {
-        let part = Part::file_async(name, path, encoding).await?;
-        self.fields.push(part);
+        self.add_file_info(name, path, encoding).await?;
         Ok(())
     }
 
-    /// Adds a file field to the form wrapping a async reader.
-    pub fn add_async_read(
+    /// Adds a file field to the form from a path, opening the file only once the form is read.
+    ///
+    /// Unlike [`Multipart::add_file`], forms that are never sent don't keep the file open, which
+    /// matters when building many forms, such as retries that may be abandoned. The file length is
+    /// read from its metadata right away, errors opening or reading the file surface as the form is read.
+    pub async fn add_file_lazy(
         &mut self,
         name: impl Into<Cow<'m, str>>,
-        filename: impl Into<Cow<'m, str>>,
-        content_type: &str,
+        path: impl AsRef<Path>,
         encoding: Option<Encoding>,
-        data: impl AsyncBufRead + Unpin + Send + Sync + 'static,
-        data_len: Option<usize>, // optional length for the async reader, if known
     ) -> Result<()> {
-        self.fields.push(Part::file_raw_async(
-            name,
-            filename,
-            content_type.parse()?,
-            encoding,
-            data,
-            data_len,
-        ));
+        let encoding = encoding.or(self.default_file_encoding);
+        let path = self.resolve_path(path.as_ref()).await?;
+        let part = Part::file_lazy(name, &path, encoding).await?;
+        let part = self.path_part(part, &path);
+        self.push_part(part);
+        Ok(())
+    }
+
+    /// Adds a file field from an already opened file.
+    ///
+    /// Accepts any async reader, so both `async_fs::File` and `async_std::fs::File` work as-is.
+    /// The content type is guessed from `filename`. Pass the file length as `data_len` if known,
+    /// otherwise the body is sent without a `Content-Length`.
+    pub fn add_open_file(
+        &mut self,
+        name: impl Into<Cow<'m, str>>,
+        filename: impl Into<Cow<'m, str>>,
+        file: impl AsyncRead + Unpin + Send + Sync + 'static,
+        data_len: Option<usize>,
+        encoding: Option<Encoding>,
+    ) {
+        let encoding = encoding.or(self.default_file_encoding);
+        self.push_part(Part::open_file(name, filename, file, data_len, encoding));
+    }
+
+    /// Adds a file field, choosing the transfer encoding from the content type guessed from the path.
+    ///
+    /// Text types (`text/*`, JSON and XML) are sent without a transfer encoding, everything else is
+    /// base64-encoded so the part survives 7bit transports. The default file encoding is not applied.
+    pub async fn add_file_auto_encoding(
+        &mut self,
+        name: impl Into<Cow<'m, str>>,
+        path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let path = self.resolve_path(path.as_ref()).await?;
+        let encoding = match mime_guess::from_path(&path).first() {
+            Some(mime) if is_text_type(&mime) => None,
+            _ => Some(Encoding::Base64),
+        };
+        let part = Part::file_async(name, &path, encoding).await?;
+        self.push_part(self.path_part(part, &path));
+        Ok(())
+    }
+
+    /// Applies the default filename to a part created from a path without a file name.
+    fn path_part(&self, mut part: Part<'m>, path: &Path) -> Part<'m> {
+        if path.file_name().is_none() {
+            part.set_filename(self.default_filename.clone());
+        }
+        part
+    }
+
+    /// Resolves a path against the base directory, if one is set.
+    pub(crate) async fn resolve_path<'a>(&self, path: &'a Path) -> Result<Cow<'a, Path>> {
+        let Some(base_dir) = &self.base_dir else {
+            return Ok(Cow::Borrowed(path));
+        };
+        let base_dir = async_fs::canonicalize(base_dir).await?;
+        let resolved = async_fs::canonicalize(base_dir.join(path)).await?;
+        within_base_dir(&base_dir, resolved, path)
+    }
+
+    /// Blocking version of [`Multipart::resolve_path`].
+    fn resolve_path_blocking<'a>(&self, path: &'a Path) -> Result<Cow<'a, Path>> {
+        let Some(base_dir) = &self.base_dir else {
+            return Ok(Cow::Borrowed(path));
+        };
+        let base_dir = std::fs::canonicalize(base_dir)?;
+        let resolved = std::fs::canonicalize(base_dir.join(path))?;
+        within_base_dir(&base_dir, resolved, path)
+    }
+
+    /// Adds a file field to the form from path, reading it with blocking `std::fs` calls.
+    ///
+    /// Intended for synchronous code, e.g. together with [`Multipart::into_bytes_blocking`].
+    /// The file is opened immediately but only read when the form is serialized.
+    pub fn add_file_blocking(
+        &mut self,
+        name: impl Into<Cow<'m, str>>,
+        path: impl AsRef<Path>,
+        encoding: Option<Encoding>,
+    ) -> Result<()> {
+        let encoding = encoding.or(self.default_file_encoding);
+        let path = self.resolve_path_blocking(path.as_ref())?;
+        let part = Part::from_path_sync(name, &path, encoding)?;
+        self.push_part(self.path_part(part, &path));
+        Ok(())
+    }
+
+    /// Adds a file field named `base_name[]` for each path, following PHP's convention for repeated fields.
+    ///
+    /// The default file encoding applies. If a file can't be added, the files before it stay in the form.
+    pub async fn add_files_array<P: AsRef<Path>>(
+        &mut self,
+        base_name: &str,
+        paths: impl IntoIterator<Item = P>,
+    ) -> Result<()> {
+        let name = format!("{}[]", base_name);
+        for path in paths {
+            self.add_file(name.clone(), path, None).await?;
+        }
+        Ok(())
+    }
+
+    /// Adds a file field to the form from path, returning the detected file metadata.
+    pub async fn add_file_info(
+        &mut self,
+        name: impl Into<Cow<'m, str>>,
+        path: impl AsRef<Path>,
+        encoding: Option<Encoding>,
+    ) -> Result<FileInfo> {
+        let encoding = encoding.or(self.default_file_encoding);
+        let path = self.resolve_path(path.as_ref()).await?;
+        let part = Part::file_async(name, &path, encoding).await?;
+        let part = self.path_part(part, &path);
+        let info = FileInfo {
+            filename: part.filename().unwrap_or_default().to_owned(),
+            mime: part.decoded_content_type().clone(),
+            len: part.data.len().unwrap_or_default() as u64,
+            encoding: part.encoding(),
+        };
+        self.push_part(part);
+        Ok(info)
+    }
+
+    /// Adds a file field to the form wrapping a async reader.
+    pub fn add_async_read(
+        &mut self,
+        name: impl Into<Cow<'m, str>>,
+        filename: impl Into<Cow<'m, str>>,
+        content_type: &str,
+        encoding: Option<Encoding>,
+        data: impl AsyncBufRead + Unpin + Send + Sync + 'static,
+        data_len: Option<usize>, // optional length for the async reader, if known
+    ) -> Result<()> {
+        let disposition = ContentDisposition::form_data(name).with_filename(filename);
+        disposition.validate()?;
+        self.push_part(Part::with_disposition(
+            disposition,
+            content_type.parse()?,
+            encoding.or(self.default_file_encoding),
+            data,
+            data_len,
+        ));
+        Ok(())
+    }
+
+    /// Adds a part from a reader with an exact `Content-Disposition` header.
+    ///
+    /// Nothing is imposed on the part: the disposition type, `name`, `filename` and extra parameters are
+    /// written as given, and neither the default file encoding nor any content type guessing is applied.
+    /// Returns an error if the disposition type or a parameter name is not a valid token.
+    pub fn add_reader_with_content_disposition(
+        &mut self,
+        disposition: ContentDisposition<'m>,
+        content_type: Mime,
+        encoding: Option<Encoding>,
+        data: impl AsyncBufRead + Unpin + Send + Sync + 'static,
+        data_len: Option<usize>,
+    ) -> Result<()> {
+        disposition.validate()?;
+        self.fields.push(Part::with_disposition(
+            disposition,
+            content_type,
+            encoding,
+            data,
+            data_len,
+        ));
+        Ok(())
+    }
+
+    /// Adds a file field to the form from an existing `Body`, without reading it into memory.
+    ///
+    /// The length of the body, if known, is kept for the size hint of the form.
+    pub fn add_body(
+        &mut self,
+        name: impl Into<Cow<'m, str>>,
+        filename: impl Into<Cow<'m, str>>,
+        content_type: &str,
+        encoding: Option<Encoding>,
+        body: Body,
+    ) -> Result<()> {
+        self.push_part(Part::file_raw(
+            name,
+            filename,
+            content_type.parse()?,
+            encoding.or(self.default_file_encoding),
+            body,
+        ));
+        Ok(())
+    }
+
+    /// Adds a file field to the form wrapping a sync reader.
+    pub fn add_sync_read(
+        &mut self,
+        name: impl Into<Cow<'m, str>>,
+        filename: impl Into<Cow<'m, str>>,
+        content_type: &str,
+        encoding: Option<Encoding>,
+        mut data: impl Read + Seek + Send + 'static,
+    ) -> Result<()> {
+        let mut buffer = Vec::new();
+        data.read_to_end(&mut buffer)?;
+        self.push_part(Part::file_raw(
+            name,
+            filename,
+            content_type.parse()?,
+            encoding.or(self.default_file_encoding),
+            buffer,
+        ));
+        Ok(())
+    }
+
+    /// Renames fields, or drops them when `f` returns `None`.
+    ///
+    /// `f` is called with the name of every named part, and the part is renamed to the returned name.
+    /// Parts without a name are kept as they are.
+    pub fn map_field_names(&mut self, f: impl Fn(&str) -> Option<String>) {
+        self.fields.retain_mut(|part| {
+            let Some(name) = part.name() else {
+                return true;
+            };
+            match f(name) {
+                Some(new_name) => {
+                    if new_name != name {
+                        part.set_name(new_name);
+                    }
+                    true
+                }
+                None => false,
+            }
+        });
+    }
+
+    /// Removes fields that share a name with another field, keeping one per name.
+    ///
+    /// Repeated names are valid in a form, e.g. several files sent under `files[]`, so this is
+    /// only useful when duplicates are unintended, such as when merging fields from several sources.
+    /// The kept fields stay in their positions. Parts without a name are kept as they are.
+    pub fn deduplicate_fields(&mut self, keep: DedupKeep) {
+        // The index of the field to keep for each name
+        let mut kept = HashMap::new();
+        for (i, part) in self.fields.iter().enumerate() {
+            if let Some(name) = part.name() {
+                match keep {
+                    DedupKeep::First => {
+                        kept.entry(name.to_owned()).or_insert(i);
+                    }
+                    DedupKeep::Last => {
+                        kept.insert(name.to_owned(), i);
+                    }
+                }
+            }
+        }
+        let mut i = 0;
+        self.fields.retain(|part| {
+            let keep = part.name().is_none_or(|name| kept[name] == i);
+            i += 1;
+            keep
+        });
+    }
+
+    /// Reduces the filename of every file part to a safe base name.
+    ///
+    /// Directory components (with either `/` or `\` separators) and control characters are removed,
+    /// so `../../etc/passwd` becomes `passwd`. Filenames left empty, `.` or `..` are replaced with the
+    /// default filename, see [`Multipart::with_default_filename`].
+    /// Use this when forwarding filenames from untrusted sources.
+    pub fn sanitize_filenames(&mut self) {
+        for part in &mut self.fields {
+            let Some(filename) = part.filename() else {
+                continue;
+            };
+            let sanitized =
+                sanitize_filename(filename).unwrap_or_else(|| self.default_filename.clone());
+            if sanitized != filename {
+                part.set_filename(sanitized);
+            }
+        }
+    }
+
+    /// Returns the parts of the form, in order.
+    #[cfg(feature = "sha256")]
+    pub(crate) fn parts(&self) -> &[Part<'m>] {
+        &self.fields
+    }
+
+    /// Checks every part's content type against an allowlist, comparing essences and ignoring parameters.
+    ///
+    /// Wildcards are supported, so `image/*` allows any image type and `*/*` allows everything.
+    /// Returns an error naming the first part whose type is not allowed.
+    pub fn validate_mime_types(&self, allowed: &[Mime]) -> Result<()> {
+        let star = mime_guess::mime::STAR;
+        for part in &self.fields {
+            let mime = part.decoded_content_type();
+            let is_allowed = allowed.iter().any(|allowed| {
+                (allowed.type_() == star || allowed.type_() == mime.type_())
+                    && (allowed.subtype() == star || allowed.subtype() == mime.subtype())
+            });
+            if !is_allowed {
+                let field = part.name().or(part.filename()).unwrap_or("<unnamed>");
+                return Err(format_err!(
+                    "Field `{}` has disallowed content type `{}`",
+                    field,
+                    mime.essence_str()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether every part is held in memory.
+    ///
+    /// A fully buffered form is cheap to send with [`Multipart::set_request_bytes`], while forms with
+    /// reader-backed parts (files, async readers or bodies) are better streamed with [`Multipart::set_request`].
+    pub fn is_fully_buffered(&self) -> bool {
+        self.fields
+            .iter()
+            .all(|part| part.data.as_bytes().is_some())
+    }
+
+    /// Returns the `(name, value)` pairs of the in-memory text fields, in order.
+    ///
+    /// File parts and parts backed by a reader are skipped. Values are the raw text before any
+    /// transfer encoding, with invalid UTF-8 replaced.
+    pub fn text_fields(&self) -> Vec<(&str, Cow<'_, str>)> {
+        self.fields
+            .iter()
+            .filter(|part| part.filename().is_none())
+            .filter_map(|part| Some((part.name()?, part.data.as_bytes()?)))
+            .map(|(name, value)| (name, String::from_utf8_lossy(value)))
+            .collect()
+    }
+
+    /// Removes the first part named `name` and returns a stream over its body, or `None` if there is no such part.
+    ///
+    /// The body is streamed as the raw data, before any transfer encoding, without the part headers.
+    /// Reader-backed parts are read as the stream is polled.
+    pub fn take_field_stream(&mut self, name: &str) -> Option<impl Stream<Item = StreamChunk>> {
+        let index = self
+            .fields
+            .iter()
+            .position(|part| part.name() == Some(name))?;
+        let part = self.fields.remove(index);
+        let buf_size = part.buf_size(self.buf_size);
+        Some(ReaderStream::new(part.data.into_reader(), buf_size, None))
+    }
+
+    /// Reorders the parts for AWS S3 browser-based POST uploads, which require the file to be the last field.
+    ///
+    /// Non-file fields keep their relative order. Returns an error if the form has more than one file part.
+    pub fn canonicalize_for_s3(&mut self) -> Result<()> {
+        let files = self
+            .fields
+            .iter()
+            .filter(|part| part.filename().is_some())
+            .count();
+        if files > 1 {
+            return Err(format_err!(
+                "S3 POST uploads accept a single file field, found {}",
+                files
+            ));
+        }
+        // Stable, so the policy fields keep their order
+        self.fields.sort_by_key(|part| part.filename().is_some());
+        Ok(())
+    }
+
+    /// Returns the serialized header block of each field, in order.
+    ///
+    /// Each block is byte-for-byte what is emitted for the part, including the blank line
+    /// that separates the headers from the body.
+    pub fn part_headers(&self) -> Vec<Vec<u8>> {
+        self.fields.iter().map(Part::header_bytes).collect()
+    }
+
+    /// Returns the `Content-Disposition` header value of each field, in order.
+    ///
+    /// The values are exactly what is emitted, including any `filename*` parameter.
+    /// Pre-serialized parts, such as those added by [`Multipart::add_http_request`],
+    /// carry their own headers and have `None`.
+    pub fn content_dispositions(&self) -> Vec<Option<String>> {
+        self.fields
+            .iter()
+            .map(|part| (part.header_len() > 0).then(|| part.content_disposition()))
+            .collect()
+    }
+
+    /// Checks whether the boundary appears in the encoded body of any in-memory part,
+    /// without changing the boundary.
+    ///
+    /// Reader-backed parts cannot be inspected without consuming them and are skipped.
+    /// Returns an error naming the first colliding field.
+    pub async fn validate_boundary_in_body(&self) -> Result<()> {
+        for field in &self.fields {
+            let Some(bytes) = field.data.as_bytes() else {
+                continue;
+            };
+            let mut encoded = bytes.to_vec();
+            if let Some(encoding) = field.encoding() {
+                encoding.encode(&mut encoded);
+            }
+            let boundary = self.boundary.as_bytes();
+            if encoded.windows(boundary.len()).any(|w| w == boundary) {
+                let field = field.name().or(field.filename()).unwrap_or("<unnamed>");
+                return Err(format_err!(
+                    "Boundary `{}` appears in the body of field `{}`",
+                    self.boundary,
+                    field
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds a request with the multipart form as its body, returning it with the boundary used.
+    ///
+    /// Useful when the boundary needs to be logged or signed alongside sending the request.
+    pub fn build_request(self, method: Method, url: Url) -> (Request, String) {
+        let boundary = self.boundary.clone();
+        let mut req = Request::new(method, url);
+        self.set_request(&mut req);
+        (req, boundary)
+    }
+
+    /// Sets the request body to the multipart form data.
+    pub fn set_request(self, req: &mut Request) {
+        self.apply_request_headers(req.as_mut());
+        req.set_body(self.into_body(None));
+    }
+
+    /// Sets the headers and body of a request like [`Multipart::set_request`], keeping the form for reuse,
+    /// e.g. for retries or sending it to several endpoints.
+    ///
+    /// The parts are copied into the request. Returns an error if a part is backed by a reader, such as
+    /// a file, since it can only be read once.
+    pub fn apply_to(&self, req: &mut Request) -> Result<()> {
+        self.try_clone()?.set_request(req);
+        Ok(())
+    }
+
+    /// Returns a copy of the form, or an error if a part is backed by a reader.
+    fn try_clone(&self) -> Result<Multipart<'m>> {
+        let mut form = self.empty_like();
+        for (i, part) in self.fields.iter().enumerate() {
+            let part = part.try_clone().ok_or_else(|| {
+                format_err!("Part {} is backed by a reader and can't be copied", i)
+            })?;
+            form.fields.push(part);
+        }
+        Ok(form)
+    }
+
+    /// Sets the request body to the multipart form data, with an `Expect: 100-continue` header.
+    ///
+    /// Clients that honor the header wait for the server to accept the headers before streaming
+    /// the body, so a rejected upload doesn't send the whole form first.
+    pub fn set_request_expect_continue(self, req: &mut Request) {
+        req.insert_header("Expect", "100-continue");
+        self.set_request(req);
+    }
+
+    /// Sets the form as the body of a response, along with the same headers as [`Multipart::set_request`].
+    ///
+    /// Useful for test servers and endpoints serving downloads, for example a `multipart/mixed`
+    /// response of attachment parts.
+    pub fn set_response(self, res: &mut Response) {
+        self.apply_request_headers(res.as_mut());
+        res.set_body(self.into_body(None));
+    }
+
+    /// Returns every header [`Multipart::set_request`] sets, for integrations that build requests themselves.
+    ///
+    /// Always contains `Content-Type`, and `Content-Length` when the length of the form is known.
+    pub fn request_headers(&self) -> Vec<(String, String)> {
+        let mut headers = vec![("Content-Type".to_string(), self.content_type())];
+        if let Some(len) = self.content_length() {
+            headers.push(("Content-Length".to_string(), len.to_string()));
+        }
+        headers
+    }
+
+    /// Returns a `curl` command sending an equivalent form to `url`, for reproducing uploads while debugging.
+    ///
+    /// Each named part becomes a `-F` argument: `-F 'name=value'` for text fields and
+    /// `-F 'name=@filename'` for files, which curl reads from the current directory. Content types
+    /// that curl wouldn't pick itself and transfer encodings are passed as `type` and `encoder`.
+    /// curl picks its own boundary, and values of reader-backed text fields can't be read without
+    /// consuming them, so they are left empty. Parts without a name and pre-serialized parts are skipped.
+    pub fn as_curl_command(&self, url: &str) -> String {
+        let mut command = String::from("curl");
+        if self.subtype != "form-data" {
+            command += &format!(
+                " -H {}",
+                shell_quote(&format!("Content-Type: multipart/{}", self.subtype))
+            );
+        }
+        for part in &self.fields {
+            let Some(name) = part.name() else {
+                continue;
+            };
+            if part.header_len() == 0 {
+                continue;
+            }
+            let (mut arg, default_type) = match part.filename() {
+                Some(filename) => (
+                    format!("{}=@{}", name, filename),
+                    mime_guess::from_path(filename).first_or_octet_stream(),
+                ),
+                None => {
+                    let value = part.data.as_bytes().unwrap_or_default();
+                    let value = String::from_utf8_lossy(value);
+                    (format!("{}={}", name, value), mime_guess::mime::TEXT_PLAIN)
+                }
+            };
+            if part.content_type.essence_str() != default_type.essence_str() {
+                arg += &format!(";type={}", part.content_type);
+            }
+            if let Some(encoding) = part.encoding() {
+                arg += &format!(";encoder={}", encoding.to_str());
+            }
+            command += &format!(" -F {}", shell_quote(&arg));
+        }
+        command + " " + &shell_quote(url)
+    }
+
+    /// Sets the headers of [`Multipart::request_headers`] on a request or response.
+    fn apply_request_headers(&self, headers: &mut Headers) {
+        // If size not known, unset any Content-Length header left from an earlier body
+        headers.remove("Content-Length");
+        for (name, value) in self.request_headers() {
+            headers.insert(name.as_str(), value);
+        }
+    }
+
+    /// Converts the form into its `Content-Type` header value and a streaming `Body`.
+    ///
+    /// The body carries the exact length when it is known, so it can be used as `Content-Length`.
+    /// Returning both together means the header can't be forgotten or go out of sync with the boundary.
+    pub fn try_into_body_with_content_type(self) -> (String, Body) {
+        let content_type = self.content_type();
+        (content_type, self.into_body(None))
+    }
+
+    pub async fn set_request_bytes(self, req: &mut Request) -> Result<()> {
+        self.apply_request_headers(req.as_mut());
+        let body = self.into_body_bytes().await?;
+        req.set_body(body);
+        Ok(())
+    }
+
+    /// Sets the form as the body of a request with an exact `Content-Length`, even when it can't be computed up front.
+    ///
+    /// The form is streamed once while counting its length, which is needed for quoted-printable
+    /// parts and readers of unknown length. Up to 8 MiB are buffered in memory, larger forms are
+    /// spooled to a temporary file that is deleted once the body is dropped, so memory use stays
+    /// bounded, unlike [`Multipart::set_request_bytes`].
+    pub async fn set_request_exact(self, req: &mut Request) -> Result<()> {
+        self.apply_request_headers(req.as_mut());
+        let body = spool(self.into_stream(None), SPOOL_MEMORY_LIMIT).await?;
+        // The spooled body always knows its length
+        if let Some(len) = body.len() {
+            req.insert_header("Content-Length", len.to_string());
+        }
+        req.set_body(body);
+        Ok(())
+    }
+
+    /// Serializes the form into bytes, blocking the current thread.
+    ///
+    /// For synchronous code; don't call this from within an async runtime.
+    pub fn into_bytes_blocking(self) -> Result<Vec<u8>> {
+        futures_lite::future::block_on(self.into_bytes())
+    }
+
+    /// Converts the multipart form to a `Body`.
+    pub async fn into_bytes(self) -> Result<Vec<u8>> {
+        let mut data: Vec<u8> = Vec::new();
+
+        if self.fields.is_empty() {
+            return Ok(data);
+        }
+
+        let first = self.boundary_delimiter(Delimiter::First);
+        let middle = self.boundary_delimiter(Delimiter::Middle);
+        let last = self.boundary_delimiter(Delimiter::Last);
+        for (i, field) in self.fields.into_iter().enumerate() {
+            // Add boundary for each field
+            data.extend_from_slice(if i == 0 { &first } else { &middle });
+            field.extend(&mut data).await?;
+        }
+
+        // Add closing boundary
+        data.extend(last);
+
+        Ok(data)
+    }
+
+    /// Converts the multipart form into a stream of byte chunks.
+    ///
+    /// Boundaries, part headers and part bodies are never merged into the same chunk:
+    /// each boundary is yielded on its own, followed by the part's header block as a single chunk,
+    /// followed by the body chunks. Consumers that batch writes can use this to flush after headers.
+    pub fn into_stream(self, buf_size: Option<usize>) -> impl Stream<Item = StreamChunk> {
+        if self.fields.is_empty() {
+            let empty_stream: Pin<Box<dyn Stream<Item = StreamChunk>>> =
+                Box::pin(futures_lite::stream::empty());
+            return empty_stream;
+        }
+
+        let head_bytes = self.boundary_delimiter(Delimiter::First);
+        let seperator = self.boundary_delimiter(Delimiter::Middle);
+        let tail = self.boundary_delimiter(Delimiter::Last);
+        let buf_sizes = self.part_buf_sizes(buf_size);
+        // Parts are streamed one after another rather than nested in a chain,
+        // so polling doesn't get deeper or slower as the number of parts grows
+        let fields = self
+            .fields
+            .into_iter()
+            .zip(buf_sizes)
+            .map(|(field, buf_size)| field.into_stream(buf_size))
+            .collect::<Vec<_>>();
+        let stream = futures_lite::stream::iter(fields.into_iter().enumerate()).flat_map(
+            move |(i, field)| {
+                let delimiter = if i == 0 {
+                    head_bytes.clone()
+                } else {
+                    seperator.clone()
+                };
+                futures_lite::stream::once(Ok(delimiter)).chain(field)
+            },
+        );
+        let end = futures_lite::stream::once(Ok(tail));
+        Box::pin(stream.chain(end)) as Pin<Box<dyn Stream<Item = StreamChunk>>>
+    }
+
+    /// Converts the multipart form into a stream of byte chunks, recording the offset, length and kind of each chunk.
+    ///
+    /// Meant for diagnosing framing problems: the log is filled in as the stream is consumed,
+    /// with one entry per chunk. The output bytes are the same as [`Multipart::into_stream`].
+    pub fn into_stream_debug(
+        self,
+        buf_size: Option<usize>,
+    ) -> (impl Stream<Item = StreamChunk>, Arc<Mutex<Vec<ChunkInfo>>>) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut tagged: Vec<TaggedSegment> = Vec::new();
+        let boundary_chunk = |bytes: Vec<u8>| {
+            let chunk = (ChunkKind::Boundary, None, Ok(bytes));
+            Box::pin(futures_lite::stream::once(chunk))
+        };
+        let head_bytes = self.boundary_delimiter(Delimiter::First);
+        let seperator = self.boundary_delimiter(Delimiter::Middle);
+        let tail = self.boundary_delimiter(Delimiter::Last);
+        let buf_sizes = self.part_buf_sizes(buf_size);
+        for (i, (field, buf_size)) in self.fields.into_iter().zip(buf_sizes).enumerate() {
+            let delimiter = if i == 0 {
+                head_bytes.clone()
+            } else {
+                seperator.clone()
+            };
+            tagged.push(boundary_chunk(delimiter));
+            let name = field.name().map(str::to_owned);
+            // Pre-serialized parts have no separate header chunk
+            let has_header = field.header_len() > 0;
+            let stream = field
+                .into_stream(buf_size)
+                .enumerate()
+                .map(move |(i, chunk)| {
+                    let kind = if i == 0 && has_header {
+                        ChunkKind::Header
+                    } else {
+                        ChunkKind::Body
+                    };
+                    (kind, name.clone(), chunk)
+                });
+            tagged.push(Box::pin(stream));
+        }
+        if !tagged.is_empty() {
+            tagged.push(boundary_chunk(tail));
+        }
+
+        let recorder = log.clone();
+        let mut offset = 0;
+        let stream =
+            futures_lite::stream::iter(tagged)
+                .flatten()
+                .map(move |(kind, field_name, chunk)| {
+                    if let Ok(bytes) = &chunk {
+                        let info = ChunkInfo {
+                            offset,
+                            len: bytes.len(),
+                            kind,
+                            field_name,
+                        };
+                        offset += bytes.len() as u64;
+                        recorder.lock().unwrap().push(info);
+                    }
+                    chunk
+                });
+        (stream, log)
+    }
+
+    /// Converts the multipart form into a stream of byte chunks, reading ahead from upcoming parts.
+    ///
+    /// While a part is being streamed, the following parts are polled as well and their chunks are
+    /// buffered, so slow sources such as files on different disks are read concurrently instead of
+    /// one after another. Reading ahead pauses once `max_prefetch` bytes are buffered.
+    ///
+    /// The output bytes are the same, and in the same order, as [`Multipart::into_stream`].
+    pub fn into_stream_prefetch(
+        self,
+        buf_size: Option<usize>,
+        max_prefetch: usize,
+    ) -> impl Stream<Item = StreamChunk> {
+        if self.fields.is_empty() {
+            let empty_stream: Pin<Box<dyn Stream<Item = StreamChunk>>> =
+                Box::pin(futures_lite::stream::empty());
+            return empty_stream;
+        }
+
+        let head_bytes = self.boundary_delimiter(Delimiter::First);
+        let seperator = self.boundary_delimiter(Delimiter::Middle);
+        let tail = self.boundary_delimiter(Delimiter::Last);
+        let buf_sizes = self.part_buf_sizes(buf_size);
+        let mut segments = Vec::with_capacity(self.fields.len() + 1);
+        for (i, (field, buf_size)) in self.fields.into_iter().zip(buf_sizes).enumerate() {
+            let delimiter = if i == 0 {
+                head_bytes.clone()
+            } else {
+                seperator.clone()
+            };
+            let segment =
+                futures_lite::stream::once(Ok(delimiter)).chain(field.into_stream(buf_size));
+            segments.push(Box::pin(segment) as Pin<Box<dyn Stream<Item = StreamChunk>>>);
+        }
+        segments.push(Box::pin(futures_lite::stream::once(Ok(tail))));
+        Box::pin(PrefetchStream::new(segments, max_prefetch))
+    }
+
+    /// Converts the multipart form into a stream of byte chunks, merging small consecutive chunks.
+    ///
+    /// Chunks are buffered until at least `coalesce_min` bytes are available, reducing the number of tiny
+    /// writes a consumer makes for headers, boundaries and short text fields. Every chunk except the last
+    /// is at least `coalesce_min` bytes; the output bytes are the same as [`Multipart::into_stream`].
+    pub fn into_stream_coalesced(
+        self,
+        buf_size: Option<usize>,
+        coalesce_min: usize,
+    ) -> impl Stream<Item = StreamChunk> {
+        let stream = self.into_stream(buf_size);
+        futures_lite::stream::unfold(Some(stream), move |stream| async move {
+            let mut stream = stream?;
+            let mut buf = Vec::new();
+            while buf.len() < coalesce_min.max(1) {
+                match stream.next().await {
+                    Some(Ok(chunk)) if buf.is_empty() => buf = chunk,
+                    Some(Ok(chunk)) => buf.extend(chunk),
+                    Some(Err(e)) => return Some((Err(e), None)),
+                    // Flush whatever remains
+                    None => return (!buf.is_empty()).then_some((Ok(buf), None)),
+                }
+            }
+            Some((Ok(buf), Some(stream)))
+        })
+    }
+
+    /// Collects the chunks of [`Multipart::into_stream`] into a list, keeping the chunk boundaries.
+    ///
+    /// For APIs that take a list of chunks; the concatenated chunks are the same as [`Multipart::into_bytes`].
+    pub async fn into_chunks(self, buf_size: Option<usize>) -> Result<Vec<Vec<u8>>> {
+        // At least a boundary, a header and a body chunk per part, and the closing boundary
+        let mut chunks = Vec::with_capacity(self.fields.len() * 3 + 1);
+        let mut stream = Box::pin(self.into_stream(buf_size));
+        while let Some(chunk) = stream.next().await {
+            chunks.push(chunk?);
+        }
+        Ok(chunks)
+    }
+
+    pub fn into_reader(self, buf_size: Option<usize>) -> impl AsyncBufRead + Send + Sync {
+        if self.fields.is_empty() {
+            return Box::pin(futures_lite::io::empty()) as Pin<Box<dyn AsyncBufRead + Send + Sync>>;
+        }
+
+        let head_bytes = self.boundary_delimiter(Delimiter::First);
+        let seperator = self.boundary_delimiter(Delimiter::Middle);
+        let tail = self.boundary_delimiter(Delimiter::Last);
+        let buf_sizes = self.part_buf_sizes(buf_size);
+        let fields = self.fields.into_iter().zip(buf_sizes).collect();
+        Self::chain_readers(fields, head_bytes, seperator, tail)
+    }
+
+    /// Chains the parts into a single reader, starting with `first_delimiter`, with `seperator`
+    /// between the parts and ending with `tail`. Each part is read with the chunk size paired with it.
+    fn chain_readers(
+        fields: Vec<(Part<'m>, Option<usize>)>,
+        first_delimiter: Vec<u8>,
+        seperator: Vec<u8>,
+        tail: Vec<u8>,
+    ) -> Pin<Box<dyn AsyncBufRead + Send + Sync>> {
+        // Parts are read one after another rather than nested in a chain,
+        // so polling doesn't get deeper or slower as the number of parts grows
+        let mut segments: Vec<Segment> = Vec::with_capacity(fields.len() * 2 + 1);
+        for (i, (field, buf_size)) in fields.into_iter().enumerate() {
+            let delimiter = if i == 0 {
+                first_delimiter.clone()
+            } else {
+                seperator.clone()
+            };
+            segments.push(Box::pin(futures_lite::io::Cursor::new(delimiter)));
+            segments.push(Box::pin(field.into_reader(buf_size)));
+        }
+        segments.push(Box::pin(futures_lite::io::Cursor::new(tail)));
+        Box::pin(SequenceReader::new(segments))
+    }
+
+    /// Converts the multipart form into a reader that starts `offset` bytes into the serialized form,
+    /// for resuming an upload after a partial send.
+    ///
+    /// Parts that end before the offset are skipped without being read, only the part containing
+    /// the offset is read up to it. Returns an error if the length of any part is unknown,
+    /// or if the offset is past the end of the form.
+    pub async fn into_reader_from(
+        self,
+        offset: usize,
+        buf_size: Option<usize>,
+    ) -> Result<impl AsyncBufRead + Send + Sync> {
+        let ranges = self
+            .byte_ranges()
+            .ok_or_else(|| format_err!("Cannot resume a form whose length is unknown"))?;
+        let total = self.size_hint().unwrap_or_default();
+        if offset > total {
+            return Err(format_err!(
+                "Offset {} is past the end of the {} byte form",
+                offset,
+                total
+            ));
+        }
+        if offset == 0 {
+            return Ok(Box::pin(self.into_reader(buf_size)) as Pin<Box<_>>);
+        }
+
+        // Whole parts before the offset, each ending where its body ends
+        let skipped = ranges
+            .iter()
+            .take_while(|range| range.body.end <= offset)
+            .count();
+        let start = skipped
+            .checked_sub(1)
+            .map_or(0, |last| ranges[last].body.end);
+        let first_delimiter = if skipped == 0 {
+            self.boundary_delimiter(Delimiter::First)
+        } else {
+            self.boundary_delimiter(Delimiter::Middle)
+        };
+        let seperator = self.boundary_delimiter(Delimiter::Middle);
+        let tail = self.boundary_delimiter(Delimiter::Last);
+        let buf_sizes = self.part_buf_sizes(buf_size);
+        let remaining = self
+            .fields
+            .into_iter()
+            .zip(buf_sizes)
+            .skip(skipped)
+            .collect();
+
+        let mut reader = Self::chain_readers(remaining, first_delimiter, seperator, tail);
+        let to_skip = (offset - start) as u64;
+        let discarded =
+            futures_lite::io::copy((&mut reader).take(to_skip), futures_lite::io::sink()).await?;
+        if discarded != to_skip {
+            return Err(format_err!("Form ended before offset {}", offset));
+        }
+        Ok(reader)
+    }
+
+    /// Streams the multipart form into a writer, returning the number of bytes written.
+    ///
+    /// Partial and interrupted writes are retried until the whole body is written.
+    /// A writer that stops accepting data fails with a `WriteZero` error, other write errors are returned as-is.
+    pub async fn write_to<W: AsyncWrite + Unpin>(self, mut writer: W) -> Result<u64> {
+        let mut stream = self.into_stream(None);
+        let mut written = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            write_all_retry(&mut writer, &chunk).await?;
+            written += chunk.len() as u64;
+        }
+        writer.flush().await?;
+        Ok(written)
+    }
+
+    /// Returns the exact length of the streamed form, running the encodings over the data.
+    ///
+    /// Unlike the size hint, this is exact for every encoding, at the cost of encoding
+    /// the in-memory parts an extra time. Reader-backed parts cannot be replayed, so they are
+    /// only supported when they are unencoded and their length is known; otherwise an error is returned.
+    pub async fn stream_len(&self) -> Result<usize> {
+        let mut size = self.delimiters_len();
+        for field in &self.fields {
+            size += field.stream_len().await?;
+        }
+        Ok(size)
+    }
+
+    /// Returns the byte ranges of each field's header and body within the serialized form.
+    ///
+    /// Returns `None` if the length of any part is unknown.
+    pub fn byte_ranges(&self) -> Option<Vec<PartRange>> {
+        let first_len = self.boundary_delimiter(Delimiter::First).len();
+        let middle_len = self.boundary_delimiter(Delimiter::Middle).len();
+        let mut offset = 0;
+        let mut ranges = Vec::with_capacity(self.fields.len());
+        for (i, field) in self.fields.iter().enumerate() {
+            offset += if i == 0 { first_len } else { middle_len };
+            let header = offset..offset + field.header_len();
+            let body = header.end..header.end + field.body_size_hint()?;
+            offset = body.end;
+            ranges.push(PartRange { header, body });
+        }
+        Some(ranges)
+    }
+
+    /// Returns the number of bytes spent on boundaries and part headers, excluding the part bodies.
+    ///
+    /// Compare against the content length to judge how much of the request is protocol overhead.
+    pub fn overhead_bytes(&self) -> usize {
+        self.delimiters_len() + self.fields.iter().map(Part::header_len).sum::<usize>()
+    }
+
+    /// Splits the form into several forms of at most `max_bytes` each, for APIs that cap the request size.
+    ///
+    /// Parts are kept whole and in order, filling each form before starting the next. Every form
+    /// keeps the boundary, subtype and settings of this one. An empty form splits into no forms.
+    /// Returns an error if the length of a part is unknown, or a single part doesn't fit in `max_bytes`
+    /// even in a form of its own.
+    pub fn split_at_size(mut self, max_bytes: u64) -> Result<Vec<Multipart<'m>>> {
+        let delimiter_len = |kind| self.boundary_delimiter(kind).len() as u64;
+        // A form with a single part has an opening and a closing boundary line
+        let single_overhead = delimiter_len(Delimiter::First) + delimiter_len(Delimiter::Last);
+        // Each further part adds one boundary line between parts
+        let between = delimiter_len(Delimiter::Middle);
+
+        let mut forms = Vec::new();
+        let mut current = self.empty_like();
+        let mut current_len = 0;
+        for part in std::mem::take(&mut self.fields) {
+            let part_len = part
+                .content_length()
+                .ok_or_else(|| format_err!("Can't split a form with a part of unknown length"))?;
+            if single_overhead + part_len > max_bytes {
+                return Err(format_err!(
+                    "Part of {} bytes doesn't fit in a form of at most {} bytes",
+                    part_len,
+                    max_bytes
+                ));
+            }
+            if current.fields.is_empty() {
+                current_len = single_overhead + part_len;
+            } else if current_len + between + part_len <= max_bytes {
+                current_len += between + part_len;
+            } else {
+                forms.push(std::mem::replace(&mut current, self.empty_like()));
+                current_len = single_overhead + part_len;
+            }
+            current.fields.push(part);
+        }
+        if !current.fields.is_empty() {
+            forms.push(current);
+        }
+        Ok(forms)
+    }
+
+    /// Returns a form without parts, with the same boundary, content type and settings.
+    fn empty_like(&self) -> Multipart<'m> {
+        Multipart {
+            boundary: self.boundary.clone(),
+            subtype: self.subtype.clone(),
+            params: self.params.clone(),
+            fields: Vec::new(),
+            default_file_encoding: self.default_file_encoding,
+            base_dir: self.base_dir.clone(),
+            default_filename: self.default_filename.clone(),
+            max_text_len: self.max_text_len,
+            normalize_text_crlf: self.normalize_text_crlf,
+            profile: self.profile,
+            buf_size: self.buf_size,
+            total_buffer_budget: self.total_buffer_budget,
+            preamble: self.preamble.clone(),
+            epilogue: self.epilogue.clone(),
+        }
+    }
+
+    /// Returns whether two forms would serialize to the same bytes, without serializing them.
+    ///
+    /// Compares the boundaries, the `Content-Type` parameters, the preambles and epilogues, and every part's headers
+    /// (name, filename, content type and encoding) and data. `Multipart` doesn't implement
+    /// `PartialEq` because reader-backed parts (files, readers and bodies) can't be compared
+    /// without consuming them: a form containing one is never structurally equal to another.
+    pub fn structural_eq(&self, other: &Multipart<'_>) -> bool {
+        self.boundary == other.boundary
+            && self.subtype == other.subtype
+            && self.params == other.params
+            && self.preamble == other.preamble
+            && self.epilogue == other.epilogue
+            && self.fields.len() == other.fields.len()
+            && self
+                .fields
+                .iter()
+                .zip(&other.fields)
+                .all(|(part, other_part)| part.structural_eq(other_part))
+    }
+
+    /// Returns the exact length of the serialized form as a `u64`, suitable for `Content-Length`.
+    ///
+    /// Returns `None` if the length of any part is unknown, or for quoted-printable parts.
+    /// Unlike a `usize`, this doesn't overflow for multi-gigabyte forms on 32-bit targets.
+    pub fn content_length(&self) -> Option<u64> {
+        let mut size = self.delimiters_len() as u64;
+        for field in &self.fields {
+            size = size.checked_add(field.content_length()?)?;
+        }
+        Some(size)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.content_length()?.try_into().ok()
+    }
+
+    /// Returns the total length of the boundary lines between and around the fields.
+    fn delimiters_len(&self) -> usize {
+        if self.fields.is_empty() {
+            return 0;
+        }
+
+        self.boundary_delimiter(Delimiter::First).len()
+            + self.boundary_delimiter(Delimiter::Middle).len() * (self.fields.len() - 1)
+            + self.boundary_delimiter(Delimiter::Last).len()
+    }
+
+    /// Returns the bytes written around the parts. Every serialization path and length
+    /// calculation goes through here, so they can't disagree on the framing.
+    ///
+    /// The CRLF before each boundary belongs to the boundary line (RFC 2046), not to the part before it.
+    fn boundary_delimiter(&self, kind: Delimiter) -> Vec<u8> {
+        let boundary = &self.boundary;
+        let delimiter = match kind {
+            // The preamble, if any, then "--" + boundary + CRLF
+            Delimiter::First => match &self.preamble {
+                Some(preamble) => format!("{}\r\n--{}\r\n", preamble, boundary),
+                None => format!("--{}\r\n", boundary),
+            },
+            // CRLF + "--" + boundary + CRLF
+            Delimiter::Middle => format!("\r\n--{}\r\n", boundary),
+            // CRLF + "--" + boundary + "--" + CRLF, then the epilogue, if any
+            Delimiter::Last => {
+                let epilogue = self.epilogue.as_deref().unwrap_or_default();
+                format!("\r\n--{}--\r\n{}", boundary, epilogue)
+            }
+        };
+        delimiter.into_bytes()
+    }
+
+    /// Converts the form into a streaming `Body`.
+    ///
+    /// The body only carries a length when it is exactly known, so clients can trust it as `Content-Length`.
+    pub(crate) fn into_body(self, buf_size: Option<usize>) -> Body {
+        let hint = self.size_hint();
+        let buf_size = buf_size.or(self.buf_size);
+        Body::from_reader(self.into_reader(buf_size), hint)
+    }
+
+    async fn into_body_bytes(self) -> Result<Body> {
+        let bytes = self.into_bytes().await?;
+        Ok(Body::from(bytes))
+    }
+}
+
+/// Percent-encodes a key or value of an `application/x-www-form-urlencoded` body.
+fn urlencode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b' ' => encoded.push('+'),
+            b'*' | b'-' | b'.' | b'_' => encoded.push(byte as char),
+            _ if byte.is_ascii_alphanumeric() => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Quotes a value for a POSIX shell, in single quotes.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Checks that a canonicalized path is inside the canonicalized base directory.
+fn within_base_dir<'a>(base_dir: &Path, resolved: PathBuf, path: &Path) -> Result<Cow<'a, Path>> {
+    if !resolved.starts_with(base_dir) {
+        return Err(format_err!(
+            "Path `{}` escapes the base directory",
+            path.display()
+        ));
+    }
+    Ok(Cow::Owned(resolved))
+}
+
+/// Returns whether a character may appear in a boundary, excluding space (RFC 2046 `bcharsnospace`).
+///
+/// Some of these, such as `=` and `:`, aren't token characters, so [`Multipart::content_type`] quotes boundaries that use them.
+fn is_boundary_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "'()+_,-./:=?".contains(c)
+}
+
+/// Checks a boundary read from a `Content-Type` header against RFC 2046.
+///
+/// Unlike the boundaries this crate generates, received ones may contain spaces, just not at the end.
+fn check_received_boundary(boundary: &str) -> Result<()> {
+    let valid = (1..=70).contains(&boundary.len())
+        && boundary.chars().all(|c| c == ' ' || is_boundary_char(c))
+        && !boundary.ends_with(' ');
+    if !valid {
+        return Err(format_err!("Invalid multipart boundary {:?}", boundary));
+    }
+    Ok(())
+}
+
+/// Counts parts, including the parts of in-memory nested `multipart/*` bodies.
+fn count_parts(parts: &[Part<'_>]) -> usize {
+    parts
+        .iter()
+        .map(|part| 1 + nested_parts(part).map_or(0, |nested| count_parts(&nested)))
+        .sum()
+}
+
+/// Parses the body of an in-memory `multipart/*` part into its parts.
+fn nested_parts(part: &Part<'_>) -> Option<Vec<Part<'static>>> {
+    let content_type = part.decoded_content_type();
+    if content_type.type_() != mime_guess::mime::MULTIPART {
+        return None;
+    }
+    let boundary = content_type.get_param("boundary")?;
+    parser::parse_nested(part.data.as_bytes()?, boundary.as_str()).ok()
+}
+
+/// Returns whether a content type is textual, and safe to send without a transfer encoding.
+fn is_text_type(mime: &Mime) -> bool {
+    mime.type_() == mime_guess::mime::TEXT
+        || mime.subtype() == mime_guess::mime::JSON
+        || mime.subtype() == mime_guess::mime::XML
+        || mime.suffix() == Some(mime_guess::mime::JSON)
+        || mime.suffix() == Some(mime_guess::mime::XML)
+}
+
+/// Strips directory components and control characters from a filename.
+///
+/// Returns `None` if no usable name is left.
+fn sanitize_filename(filename: &str) -> Option<String> {
+    let base = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let base = base.chars().filter(|c| !c.is_control()).collect::<String>();
+    match base.trim() {
+        "" | "." | ".." => None,
+        _ => Some(base),
+    }
+}
+
+/// Writes the whole buffer, retrying short and interrupted writes.
+async fn write_all_retry<W: AsyncWrite + Unpin>(writer: &mut W, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        match writer.write(buf).await {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "writer closed before the whole body was written",
+                ))
+            }
+            Ok(n) => buf = &buf[n..],
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether a byte is allowed in an RFC 2045 token.
+pub(crate) fn is_token_char(b: u8) -> bool {
+    b.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?=".contains(&b)
+}
+
+/// Replaces lone `\r` and `\n` with `\r\n`, leaving existing `\r\n` pairs alone.
+fn normalize_crlf(value: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(value.len());
+    let mut bytes = value.iter().peekable();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'\r' => {
+                bytes.next_if_eq(&&b'\n');
+                out.extend_from_slice(b"\r\n");
+            }
+            b'\n' => out.extend_from_slice(b"\r\n"),
+            _ => out.push(byte),
+        }
+    }
+    out
+}
+
+/// Quotes a parameter value unless it is a valid token.
+pub(crate) fn quote_param(value: &str) -> Cow<'_, str> {
+    if !value.is_empty() && value.bytes().all(is_token_char) {
+        return Cow::Borrowed(value);
+    }
+    // Line breaks can't be escaped in a quoted string, encode them so they can't inject header lines
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+    Cow::Owned(format!("\"{}\"", escaped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Helper to create a Multipart with fixed boundary and sample text fields.
+    fn create_multipart<'a>() -> Multipart<'a> {
+        let mut m = Multipart::new();
+        // Override the randomly generated boundary for consistency in tests.
+        m.boundary = "test-boundary".into();
+        m.add_text("field1", "value1");
+        m.add_text("field2", "value2");
+        m
+    }
+
+    #[async_std::test]
+    async fn test_with_fields() -> Result<()> {
+        let multipart = Multipart::with_fields(vec![("a", "1"), ("b", "2"), ("c", "3")]);
+        let parts = multipart
+            .fields
+            .iter()
+            .map(|part| (part.name(), part.data.as_bytes()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parts,
+            [
+                (Some("a"), Some(&b"1"[..])),
+                (Some("b"), Some(&b"2"[..])),
+                (Some("c"), Some(&b"3"[..])),
+            ]
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_canonicalize_for_s3() -> Result<()> {
+        let mut m = Multipart::new();
+        m.add_file_bytes("file", "photo.jpg", b"jpeg", "image/jpeg", None)?;
+        m.add_text("key", "uploads/photo.jpg");
+        m.add_text("policy", "eyJ...");
+        m.canonicalize_for_s3()?;
+
+        let names = m.fields.iter().map(Part::name).collect::<Vec<_>>();
+        assert_eq!(names, [Some("key"), Some("policy"), Some("file")]);
+
+        m.add_file_bytes("other", "other.jpg", b"jpeg", "image/jpeg", None)?;
+        assert!(m.canonicalize_for_s3().is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_text_typed() -> Result<()> {
+        let mut m = Multipart::new();
+        m.add_text_typed(
+            "note",
+            "caf\u{e9}",
+            "text/plain; charset=us-ascii; format=flowed".parse()?,
+            Some("utf-8"),
+            Some(Encoding::QuotedPrintable),
+        )?;
+        let header = String::from_utf8(m.part_headers().remove(0))?;
+        assert!(header.contains("Content-Type: text/plain; format=flowed; charset=utf-8\r\n"));
+        assert!(header.contains("Content-Transfer-Encoding: quoted-printable\r\n"));
+        assert!(!header.contains("us-ascii"));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_build_request() -> Result<()> {
+        let (req, boundary) = create_multipart()
+            .build_request(Method::Post, Url::parse("http://example.com/upload")?);
+        assert_eq!(boundary, "test-boundary");
+        let content_type = req.content_type().unwrap();
+        assert_eq!(
+            content_type.param("boundary").map(|b| b.as_str()),
+            Some(boundary.as_str())
+        );
+        assert_eq!(req.method(), Method::Post);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_text_fields() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_enc_text("field3", "value3", Encoding::Base64);
+        m.add_file_bytes("file", "file.txt", "contents", "text/plain", None)?;
+        assert_eq!(
+            m.text_fields(),
+            [
+                ("field1", "value1".into()),
+                ("field2", "value2".into()),
+                ("field3", "value3".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_with_subtype() -> Result<()> {
+        let m = create_multipart().with_subtype("mixed")?;
+        assert_eq!(m.content_type(), "multipart/mixed; boundary=test-boundary");
+
+        let mut req = Request::new(Method::Post, Url::parse("http://example.com")?);
+        m.set_request(&mut req);
+        assert_eq!(req.content_type().unwrap().subtype(), "mixed");
+
+        assert!(Multipart::new().with_subtype("").is_err());
+        assert!(Multipart::new().with_subtype("mixed; x=y").is_err());
+        Ok(())
+    }
+
+    /// A writer that accepts at most a few bytes per call and is interrupted every other call.
+    struct TrickleWriter {
+        written: Vec<u8>,
+        calls: usize,
+        capacity: usize,
+    }
+
+    impl AsyncWrite for TrickleWriter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<io::Result<usize>> {
+            self.calls += 1;
+            if self.calls.is_multiple_of(2) {
+                return std::task::Poll::Ready(Err(io::ErrorKind::Interrupted.into()));
+            }
+            let n = buf.len().min(3).min(self.capacity - self.written.len());
+            self.written.extend_from_slice(&buf[..n]);
+            std::task::Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(
+            self: Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(
+            self: Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[async_std::test]
+    async fn test_write_to_short_writes() -> Result<()> {
+        let expected = create_multipart().into_bytes().await?;
+        let mut writer = TrickleWriter {
+            written: Vec::new(),
+            calls: 0,
+            capacity: usize::MAX,
+        };
+        let written = create_multipart().write_to(&mut writer).await?;
+        assert_eq!(written, expected.len() as u64);
+        assert_eq!(writer.written, expected);
+
+        let closed = TrickleWriter {
+            written: Vec::new(),
+            calls: 0,
+            capacity: 10,
+        };
+        let err = create_multipart().write_to(closed).await.unwrap_err();
+        let err = err.downcast::<io::Error>().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_open_file() -> Result<()> {
+        let len = std::fs::metadata("Cargo.toml")?.len() as usize;
+        let mut expected = Multipart::new();
+        expected.boundary = "test-boundary".into();
+        expected.add_file("file", "Cargo.toml", None).await?;
+
+        let mut from_async_std = Multipart::new();
+        from_async_std.boundary = "test-boundary".into();
+        let file = async_std::fs::File::open("Cargo.toml").await?;
+        from_async_std.add_open_file("file", "Cargo.toml", file, Some(len), None);
+
+        let mut from_async_fs = Multipart::new();
+        from_async_fs.boundary = "test-boundary".into();
+        let file = async_fs::File::open("Cargo.toml").await?;
+        from_async_fs.add_open_file("file", "Cargo.toml", file, Some(len), None);
+
+        assert_eq!(from_async_std.size_hint(), expected.size_hint());
+        let expected = expected.into_bytes().await?;
+        assert_eq!(from_async_std.into_bytes().await?, expected);
+        assert_eq!(from_async_fs.into_bytes().await?, expected);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_sanitize_filenames() -> Result<()> {
+        let mut m = Multipart::new();
+        m.add_file_bytes("a", "../../etc/passwd", b"", "text/plain", None)?;
+        m.add_file_bytes(
+            "b",
+            "C:\\Users\\me\\report\r\n.pdf",
+            b"",
+            "text/plain",
+            None,
+        )?;
+        m.add_file_bytes("c", "uploads/..", b"", "text/plain", None)?;
+        m.add_text("d", "../not/a/filename");
+        m.sanitize_filenames();
+
+        let filenames = m.fields.iter().map(Part::filename).collect::<Vec<_>>();
+        assert_eq!(
+            filenames,
+            [Some("passwd"), Some("report.pdf"), Some("file"), None]
+        );
+        assert_eq!(m.text_fields(), [("d", "../not/a/filename".into())]);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_into_stream_coalesced() -> Result<()> {
+        let expected = create_multipart().into_bytes().await?;
+
+        let chunks = create_multipart()
+            .into_stream_coalesced(Some(4), 64)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let (last, rest) = chunks.split_last().unwrap();
+        assert!(!rest.is_empty());
+        assert!(rest.iter().all(|chunk| chunk.len() >= 64));
+        assert!(!last.is_empty());
+        assert_eq!(chunks.concat(), expected);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_overhead_bytes() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_file_bytes(
+            "file",
+            "a.bin",
+            [0u8; 100],
+            "application/octet-stream",
+            None,
+        )?;
+        m.add_enc_text("encoded", "hello", Encoding::Base64);
+
+        let bodies = m
+            .fields
+            .iter()
+            .map(|part| part.body_size_hint().unwrap())
+            .sum::<usize>();
+        assert_eq!(m.overhead_bytes() + bodies, m.size_hint().unwrap());
+        assert_eq!(Multipart::new().overhead_bytes(), 0);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_with_base_dir() -> Result<()> {
+        let mut m = Multipart::new().with_base_dir("src");
+        m.add_file("lib", "lib.rs", None).await?;
+        assert_eq!(m.fields[0].filename(), Some("lib.rs"));
+
+        let err = m.add_file("secret", "../Cargo.toml", None).await;
+        assert!(err
+            .unwrap_err()
+            .to_string()
+            .contains("escapes the base directory"));
+        assert_eq!(m.fields.len(), 1);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_is_fully_buffered() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_file_bytes("file", "a.txt", "contents", "text/plain", None)?;
+        assert!(m.is_fully_buffered());
+
+        m.add_file("avatar", "Cargo.toml", None).await?;
+        assert!(!m.is_fully_buffered());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_file_auto_encoding() -> Result<()> {
+        let png = std::env::temp_dir().join("http-client-multipart-auto-encoding.png");
+        std::fs::write(&png, b"\x89PNG\r\n\x1a\n")?;
+
+        let mut m = Multipart::new().with_default_file_encoding(Encoding::QuotedPrintable);
+        m.add_file_auto_encoding("image", &png).await?;
+        m.add_file_auto_encoding("notes", "examples/file.txt")
+            .await?;
+        std::fs::remove_file(&png)?;
+
+        assert_eq!(m.fields[0].encoding(), Some(Encoding::Base64));
+        assert_eq!(m.fields[1].encoding(), None);
+        let headers = m.part_headers();
+        assert!(String::from_utf8_lossy(&headers[0]).contains("Content-Transfer-Encoding: base64"));
+        assert!(!String::from_utf8_lossy(&headers[1]).contains("Content-Transfer-Encoding"));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_empty_file_part() -> Result<()> {
+        let path = std::env::temp_dir().join("http-client-multipart-empty.txt");
+        std::fs::write(&path, b"")?;
+
+        let mut m = Multipart::new();
+        m.boundary = "test-boundary".into();
+        m.add_file("empty", &path, None).await?;
+        let header = m.part_headers().remove(0);
+        assert_eq!(m.fields[0].body_size_hint(), Some(0));
+        assert_eq!(m.size_hint(), Some(m.overhead_bytes()));
+        let size_hint = m.size_hint();
+
+        let body = m.into_bytes().await?;
+        std::fs::remove_file(&path)?;
+        let mut expected = b"--test-boundary\r\n".to_vec();
+        expected.extend(&header);
+        expected.extend(b"\r\n--test-boundary--\r\n");
+        assert_eq!(body, expected);
+        assert_eq!(size_hint, Some(body.len()));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_with_boundary_prefix() -> Result<()> {
+        let a = Multipart::new().with_boundary_prefix("----MyApp")?;
+        let b = Multipart::new().with_boundary_prefix("----MyApp")?;
+        assert!(a.boundary.starts_with("----MyApp"));
+        assert_eq!(a.boundary.len(), "----MyApp".len() + 30);
+        assert!(a.boundary["----MyApp".len()..]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(a.boundary, b.boundary);
+
+        let long = Multipart::new().with_boundary_prefix(&"-".repeat(50))?;
+        assert_eq!(long.boundary.len(), 70);
+        assert!(Multipart::new()
+            .with_boundary_prefix(&"-".repeat(55))
+            .is_err());
+        assert!(Multipart::new().with_boundary_prefix("my app").is_err());
+        assert!(Multipart::new().with_boundary_prefix("a\"b").is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_stream_many_small_files() -> Result<()> {
+        let form = || -> Result<Multipart<'static>> {
+            let mut m = Multipart::new();
+            m.boundary = "test-boundary".into();
+            for i in 0..500 {
+                let name = format!("file{}", i);
+                let filename = format!("{}.txt", name);
+                m.add_file_bytes(name, filename, i.to_string(), "text/plain", None)?;
+            }
+            Ok(m)
+        };
+        let expected = form()?.into_bytes().await?;
+
+        let start = std::time::Instant::now();
+        let mut stream = form()?.into_stream(None);
+        let mut streamed = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            streamed.extend(chunk?);
+        }
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        assert_eq!(streamed, expected);
+        assert_eq!(
+            Multipart::from_bytes_limited(&streamed, "test-boundary", 500, 3)?
+                .fields
+                .len(),
+            500
+        );
+
+        let start = std::time::Instant::now();
+        let mut read = Vec::new();
+        form()?.into_reader(None).read_to_end(&mut read).await?;
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        assert_eq!(read, expected);
+
+        // Resuming reads through the same sequence of parts
+        let mut resumed = Vec::new();
+        form()?
+            .into_reader_from(expected.len() / 2, None)
+            .await?
+            .read_to_end(&mut resumed)
+            .await?;
+        assert_eq!(resumed, expected[expected.len() / 2..]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_file_blocking() -> Result<()> {
+        let mut m = Multipart::new().with_base_dir("examples");
+        m.boundary = "test-boundary".into();
+        m.add_file_blocking("notes", "file.txt", None)?;
+        assert!(m
+            .add_file_blocking("secret", "../Cargo.toml", None)
+            .is_err());
+        let size_hint = m.size_hint();
+
+        let body = m.into_bytes_blocking()?;
+        let contents = std::fs::read("examples/file.txt")?;
+        assert!(body.windows(contents.len()).any(|w| w == contents));
+        assert!(String::from_utf8_lossy(&body).contains("filename=\"file.txt\""));
+        assert_eq!(size_hint, Some(body.len()));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_with_default_filename() -> Result<()> {
+        let mut m = Multipart::new().with_default_filename("upload.bin");
+        m.add_file_info("dir", "src/..", None).await?;
+        m.add_file_blocking("blocking", "src/..", None)?;
+        m.add_file("named", "Cargo.toml", None).await?;
+        m.add_file_bytes("c", "uploads/..", b"", "text/plain", None)?;
+        m.sanitize_filenames();
+
+        let filenames = m.fields.iter().map(Part::filename).collect::<Vec<_>>();
+        assert_eq!(
+            filenames,
+            [
+                Some("upload.bin"),
+                Some("upload.bin"),
+                Some("Cargo.toml"),
+                Some("upload.bin")
+            ]
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_map_field_names() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_file_bytes("avatar", "me.png", b"png", "image/png", None)?;
+        m.map_field_names(|name| match name {
+            "avatar" => Some("profile_picture".into()),
+            "field1" => None,
+            other => Some(other.into()),
+        });
+
+        let names = m.fields.iter().map(Part::name).collect::<Vec<_>>();
+        assert_eq!(names, [Some("field2"), Some("profile_picture")]);
+        let header = String::from_utf8(m.part_headers().remove(1))?;
+        assert!(header.contains("name=\"profile_picture\"; filename=\"me.png\""));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_files_array() -> Result<()> {
+        let mut m = Multipart::new();
+        m.add_files_array("files", ["Cargo.toml", "examples/file.txt"])
+            .await?;
+
+        let headers = m.part_headers();
+        assert_eq!(headers.len(), 2);
+        for (header, filename) in headers.iter().zip(["Cargo.toml", "file.txt"]) {
+            let header = String::from_utf8_lossy(header);
+            assert!(header.starts_with(&format!(
+                "Content-Disposition: form-data; name=\"files[]\"; filename=\"{}\"",
+                filename
+            )));
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_into_reader_from() -> Result<()> {
+        let form = || -> Result<Multipart<'static>> {
+            let mut m = create_multipart();
+            m.add_file_bytes(
+                "file",
+                "a.bin",
+                [7u8; 100],
+                "application/octet-stream",
+                None,
+            )?;
+            m.add_enc_text("encoded", "hello world", Encoding::Base64);
+            Ok(m)
+        };
+        let expected = form()?.into_bytes().await?;
+        let ranges = form()?.byte_ranges().unwrap();
+
+        let offsets = [
+            0,
+            1,
+            ranges[1].body.end,
+            ranges[2].body.start + 10,
+            expected.len() - 3,
+        ];
+        for offset in offsets.into_iter().chain([expected.len()]) {
+            let mut reader = form()?.into_reader_from(offset, Some(8)).await?;
+            let mut resumed = Vec::new();
+            reader.read_to_end(&mut resumed).await?;
+            assert_eq!(resumed, &expected[offset..], "offset {}", offset);
+        }
+        assert!(form()?
+            .into_reader_from(expected.len() + 1, None)
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_validate_mime_types() -> Result<()> {
+        let images: Vec<Mime> = vec!["image/*".parse()?];
+        let mut m = Multipart::new();
+        m.add_file_bytes("photo", "a.png", b"png", "image/png", None)?;
+        m.add_file_bytes("scan", "b.jpg", b"jpg", "image/jpeg; q=1", None)?;
+        m.validate_mime_types(&images)?;
+
+        m.add_file_bytes("notes", "c.txt", b"text", "text/plain", None)?;
+        let err = m.validate_mime_types(&images).unwrap_err();
+        assert!(err.to_string().contains("`notes`"));
+        assert!(err.to_string().contains("text/plain"));
+
+        m.validate_mime_types(&[mime_guess::mime::STAR_STAR])?;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_part_ordering() -> Result<()> {
+        let mut m = create_multipart();
+        m.prepend_text("first", "1");
+        m.insert_text(2, "middle", "2")?;
+        assert!(m.insert_text(5, "oob", "x").is_err());
+
+        m.add_file_bytes("file", "a.txt", "contents", "text/plain", None)?;
+        m.move_part(m.len() - 1, 1)?;
+        assert!(m.move_part(0, m.len()).is_err());
+        assert!(Multipart::new().move_part(0, 0).is_err());
+
+        let names = m.fields.iter().map(Part::name).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                Some("first"),
+                Some("file"),
+                Some("field1"),
+                Some("middle"),
+                Some("field2")
+            ]
+        );
+        let body = String::from_utf8(m.into_bytes().await?)?;
+        assert!(body
+            .starts_with("--test-boundary\r\nContent-Disposition: form-data; name=\"first\"\r\n"));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_content_length() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_enc_text("encoded", "hello", Encoding::Base64);
+        assert_eq!(m.content_length(), m.size_hint().map(|n| n as u64));
+        let len = m.content_length();
+        assert_eq!(len, Some(m.into_bytes().await?.len() as u64));
+
+        // A declared length whose base64 expansion doesn't fit in a 32-bit usize
+        let declared = u32::MAX as usize / 2;
+        let mut m = Multipart::new();
+        let reader = futures_lite::io::Cursor::new(Vec::new());
+        m.add_async_read(
+            "big",
+            "big.bin",
+            "application/octet-stream",
+            Some(Encoding::Base64),
+            reader,
+            Some(declared),
+        )?;
+        let len = m.content_length().unwrap();
+        assert!(len > (declared as u64) * 4 / 3);
+        assert!(len > u32::MAX as u64 / 2 * 4 / 3);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_into_stream_prefetch_matches_sequential() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("prefetch-{}", random_alphanumeric(8)));
+        std::fs::create_dir_all(&dir)?;
+        let mut paths = Vec::new();
+        for i in 0..3u8 {
+            let path = dir.join(format!("file{i}.bin"));
+            let data = (0..5000u32).map(|n| (n as u8) ^ i).collect::<Vec<_>>();
+            std::fs::write(&path, data)?;
+            paths.push(path);
+        }
+
+        let build = || async {
+            let mut m = create_multipart();
+            for (i, path) in paths.iter().enumerate() {
+                m.add_file(format!("file{i}"), path, None).await?;
+            }
+            m.add_enc_text("after", "value", Encoding::Base64);
+            Result::Ok(m)
+        };
+
+        let sequential = build().await?.into_bytes().await?;
+        for max_prefetch in [0, 100, 1 << 20] {
+            let mut stream = Box::pin(build().await?.into_stream_prefetch(Some(512), max_prefetch));
+            let mut prefetched = Vec::new();
+            while let Some(chunk) = stream.next().await {
+                prefetched.extend(chunk?);
+            }
+            assert_eq!(prefetched, sequential);
+        }
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_data_uri() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_data_uri(
+            "note",
+            "note.txt",
+            "data:text/plain;base64,aGVsbG8gd29ybGQ=",
+        )?;
+        let part = &m.fields[2];
+        assert_eq!(part.filename(), Some("note.txt"));
+        assert_eq!(part.decoded_content_type().essence_str(), "text/plain");
+        assert_eq!(part.data.as_bytes(), Some(&b"hello world"[..]));
+        let body = String::from_utf8(m.into_bytes().await?).unwrap();
+        assert!(body.contains("Content-Type: text/plain\r\n\r\nhello world\r\n"));
+
+        let mut m = Multipart::new();
+        m.add_data_uri("default", "a.txt", "DATA:;base64,aGk")?;
+        assert_eq!(
+            m.fields[0].decoded_content_type().to_string(),
+            "text/plain;charset=us-ascii"
+        );
+
+        for uri in [
+            "text/plain;base64,aGk=",
+            "data:text/plain;base64",
+            "data:text/plain,hi",
+            "data:not a type;base64,aGk=",
+            "data:text/plain;base64,!!!",
+        ] {
+            assert!(m.add_data_uri("bad", "bad.txt", uri).is_err(), "{uri}");
+        }
+        assert_eq!(m.len(), 1);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_try_into_body_with_content_type() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_enc_text("encoded", "hello", Encoding::Base64);
+        let expected_len = m.size_hint();
+
+        let (content_type, body) = {
+            let mut m = create_multipart();
+            m.add_enc_text("encoded", "hello", Encoding::Base64);
+            m
+        }
+        .try_into_body_with_content_type();
+        assert_eq!(content_type, "multipart/form-data; boundary=test-boundary");
+        assert_eq!(body.len(), expected_len);
+        assert_eq!(body.into_bytes().await?, m.into_bytes().await?);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_reader_with_content_disposition() -> Result<()> {
+        let mut m = create_multipart();
+        let disposition = ContentDisposition::attachment()
+            .with_filename("report.csv")
+            .with_param("modification-date", "Wed, 12 Feb 1997 16:29:51 -0500")
+            .with_param("size", "7");
+        let reader = futures_lite::io::Cursor::new(b"a,b\n1,2".to_vec());
+        m.add_reader_with_content_disposition(
+            disposition,
+            "text/csv".parse()?,
+            Some(Encoding::Base64),
+            reader,
+            Some(7),
+        )?;
+
+        let expected = "Content-Disposition: attachment; filename=\"report.csv\"; \
+                        modification-date=\"Wed, 12 Feb 1997 16:29:51 -0500\"; size=7\r\n\
+                        Content-Type: text/csv\r\n\
+                        Content-Transfer-Encoding: base64\r\n\r\n";
+        assert_eq!(m.fields[2].header_bytes(), expected.as_bytes());
+        assert_eq!(m.fields[2].header_len(), expected.len());
+
+        let size_hint = m.size_hint();
+        let body = String::from_utf8(m.into_bytes().await?).unwrap();
+        assert!(body.contains(&format!("{expected}YSxiCjEsMg==\r\n")));
+        assert_eq!(size_hint, Some(body.len()));
+
+        let mut m = Multipart::new();
+        let reader = futures_lite::io::Cursor::new(Vec::new());
+        let bad = ContentDisposition::new("form data");
+        let mime = mime_guess::mime::TEXT_PLAIN;
+        assert!(m
+            .add_reader_with_content_disposition(bad, mime, None, reader, None)
+            .is_err());
+        assert!(m.is_empty());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_structural_eq() -> Result<()> {
+        let build = || -> Result<Multipart<'static>> {
+            let mut m = create_multipart();
+            m.add_enc_text("encoded", "hello", Encoding::Base64);
+            m.add_file_bytes(
+                "file",
+                "a.bin",
+                [0u8, 1, 2],
+                "application/octet-stream",
+                None,
+            )?;
+            Ok(m)
+        };
+        assert!(build()?.structural_eq(&build()?));
+
+        let mut renamed = build()?;
+        renamed.map_field_names(|name| Some(name.replace("file", "other")));
+        assert!(!build()?.structural_eq(&renamed));
+
+        let mut extra = build()?;
+        extra.add_text("extra", "value");
+        assert!(!build()?.structural_eq(&extra));
+
+        let mut other_boundary = build()?;
+        other_boundary.boundary = "other-boundary".into();
+        assert!(!build()?.structural_eq(&other_boundary));
+
+        // Reader-backed parts can't be compared
+        let with_reader = || -> Result<Multipart<'static>> {
+            let mut m = create_multipart();
+            let reader = futures_lite::io::Cursor::new(b"data".to_vec());
+            m.add_async_read("r", "r.txt", "text/plain", None, reader, Some(4))?;
+            Ok(m)
+        };
+        assert!(!with_reader()?.structural_eq(&with_reader()?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_headers() -> Result<()> {
+        let m = create_multipart();
+        let headers = m.request_headers();
+        let content_length = m.content_length().unwrap().to_string();
+        assert_eq!(
+            headers,
+            [
+                (
+                    "Content-Type".to_string(),
+                    "multipart/form-data; boundary=test-boundary".to_string()
+                ),
+                ("Content-Length".to_string(), content_length),
+            ]
+        );
+
+        let mut m = create_multipart();
+        let reader = futures_lite::io::Cursor::new(b"data".to_vec());
+        m.add_async_read("r", "r.txt", "text/plain", None, reader, None)?;
+        let headers = m.request_headers();
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[0].0, "Content-Type");
+
+        // `set_request` sets exactly these headers, dropping a stale length
+        let mut req = Request::new(Method::Post, Url::parse("http://example.com")?);
+        req.insert_header("Content-Length", "3");
+        m.set_request(&mut req);
+        assert_eq!(req["Content-Type"], headers[0].1.as_str());
+        assert!(req.header("Content-Length").is_none());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_set_response_attachment_disposition() -> Result<()> {
+        let mut m = create_multipart().with_subtype("mixed")?;
+        m.add_attachment("résumé.pdf", b"%PDF", "application/pdf", None)?;
+        let content_type = m.content_type();
+
+        let mut res = Response::new(200);
+        m.set_response(&mut res);
+        assert_eq!(res["Content-Type"], content_type.as_str());
+        let content_length = res["Content-Length"].as_str().parse::<usize>()?;
+        let body = res.body_bytes().await?;
+        assert_eq!(content_length, body.len());
+
+        let text = String::from_utf8_lossy(&body);
+        assert!(text.contains(
+            "Content-Disposition: attachment; filename=\"r_sum_.pdf\"; \
+             filename*=UTF-8''r%C3%A9sum%C3%A9.pdf\r\n"
+        ));
+        // `filename*` is preferred when reading the part back
+        let parsed = Multipart::from_bytes_limited(&body, "test-boundary", 10, 1024)?;
+        assert_eq!(parsed.fields[2].filename(), Some("résumé.pdf"));
+        assert_eq!(parsed.fields[2].name(), None);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_set_request_expect_continue() -> Result<()> {
+        let m = create_multipart();
+        let content_type = m.content_type();
+        let expected = create_multipart().into_bytes().await?;
+
+        let mut req = Request::new(Method::Post, Url::parse("http://example.com")?);
+        m.set_request_expect_continue(&mut req);
+        assert_eq!(req["Expect"], "100-continue");
+        assert_eq!(req["Content-Type"], content_type.as_str());
+        assert_eq!(req.body_bytes().await?, expected);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_deduplicate_fields() -> Result<()> {
+        for (keep, value) in [(DedupKeep::First, "1"), (DedupKeep::Last, "3")] {
+            let mut m = create_multipart();
+            m.add_text("dup", "1");
+            m.add_text("dup", "2");
+            m.add_text("other", "x");
+            m.add_text("dup", "3");
+            m.deduplicate_fields(keep);
+
+            let names = m
+                .fields
+                .iter()
+                .map(|f| f.name().unwrap())
+                .collect::<Vec<_>>();
+            let dup = match keep {
+                DedupKeep::First => ["field1", "field2", "dup", "other"],
+                DedupKeep::Last => ["field1", "field2", "other", "dup"],
+            };
+            assert_eq!(names, dup);
+            let field = m.fields.iter().find(|f| f.name() == Some("dup")).unwrap();
+            assert_eq!(field.data.as_bytes(), Some(value.as_bytes()));
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_preamble_and_epilogue() -> Result<()> {
+        let preamble = "This is a multi-part message in MIME format.";
+        let form = || {
+            create_multipart()
+                .with_preamble(preamble)
+                .with_epilogue("That was all.\r\n")
+        };
+        let m = form();
+        let expected_size = m.size_hint().unwrap();
+        let ranges = m.byte_ranges().unwrap();
+        let bytes = m.into_bytes().await?;
+        assert_eq!(bytes.len(), expected_size);
+
+        let text = String::from_utf8(bytes.clone()).unwrap();
+        assert!(text.starts_with(&format!("{}\r\n--test-boundary\r\n", preamble)));
+        assert!(text.ends_with("\r\n--test-boundary--\r\nThat was all.\r\n"));
+        assert_eq!(&bytes[ranges[0].body.clone()], b"value1");
+
+        // Every serialization path agrees
+        let mut streamed = Vec::new();
+        let mut stream = Box::pin(form().into_stream(Some(4)));
+        while let Some(chunk) = stream.next().await {
+            streamed.extend(chunk?);
+        }
+        assert_eq!(streamed, bytes);
+        let mut read = Vec::new();
+        form().into_reader(None).read_to_end(&mut read).await?;
+        assert_eq!(read, bytes);
+
+        // Readers skip both
+        let parsed = Multipart::from_bytes_limited(&bytes, "test-boundary", 10, 1024)?;
+        assert!(parsed.structural_eq(&create_multipart()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_related_start_info() {
+        let m = Multipart::related(
+            "application/xop+xml",
+            Some("<root@example.com>"),
+            Some("application/soap+xml"),
+        );
+        let content_type = m.content_type();
+        assert!(content_type.starts_with("multipart/related; boundary="));
+        assert!(content_type.ends_with(
+            "; type=\"application/xop+xml\"; start=\"<root@example.com>\"; \
+             start-info=\"application/soap+xml\""
+        ));
+
+        let m = Multipart::related("text/html", None, None);
+        assert!(m.content_type().ends_with("; type=\"text/html\""));
+    }
+
+    #[async_std::test]
+    async fn test_as_curl_command() -> Result<()> {
+        let mut m = Multipart::new();
+        m.add_text("name", "John Doe");
+        m.add_text("quote", "it's");
+        m.add_text_mime("json", "{}", "application/json")?;
+        m.add_file("avatar", "Cargo.toml", Some(Encoding::Base64))
+            .await?;
+        let command = m.as_curl_command("http://example.com/upload");
+        assert_eq!(
+            command,
+            "curl -F 'name=John Doe' -F 'quote=it'\\''s' -F 'json={};type=application/json' \
+             -F 'avatar=@Cargo.toml;encoder=base64' 'http://example.com/upload'"
+        );
+        assert!(command.contains("-F 'name=John Doe'"));
+        assert!(command.contains("-F 'avatar=@Cargo.toml"));
+
+        let mut m = Multipart::mixed();
+        m.add_text("name", "John Doe");
+        m.add_file("avatar", "Cargo.toml", None).await?;
+        let command = m.as_curl_command("http://example.com");
+        assert!(command.starts_with("curl -H 'Content-Type: multipart/mixed' "));
+        assert!(command.contains("-F 'name=John Doe'"));
+        assert!(command.contains("-F 'avatar=@Cargo.toml'"));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_file_lazy() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("multipart-lazy-{}", random_alphanumeric(8)));
+        std::fs::create_dir(&dir)?;
+        let path = dir.join("lazy.txt");
+        std::fs::write(&path, "lazy contents")?;
+
+        let mut m = create_multipart();
+        m.add_file_lazy("file", &path, None).await?;
+        let expected_size = m.size_hint().unwrap();
+        let bytes = m.into_bytes().await?;
+        assert_eq!(bytes.len(), expected_size);
+        assert!(String::from_utf8(bytes).unwrap().contains(
+            "filename=\"lazy.txt\"\r\nContent-Type: text/plain\r\n\r\nlazy contents\r\n"
+        ));
+
+        // The file isn't opened when it is added, only when the form is read
+        let mut m = create_multipart();
+        m.add_file_lazy("file", &path, None).await?;
+        std::fs::remove_file(&path)?;
+        std::fs::remove_dir(&dir)?;
+        let mut stream = Box::pin(m.into_stream(None));
+        let mut result = Ok(());
+        while let Some(chunk) = stream.next().await {
+            if let Err(e) = chunk {
+                result = Err(e);
+                break;
+            }
+        }
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_serialization_paths_identical() -> Result<()> {
+        for framed in [false, true] {
+            let form = || -> Result<Multipart<'static>> {
+                let mut m = create_multipart();
+                // Data that looks like framing must not confuse any path
+                m.add_file_bytes(
+                    "file",
+                    "a.bin",
+                    b"\r\n--\r\n",
+                    "application/octet-stream",
+                    None,
+                )?;
+                Ok(if framed {
+                    m.with_preamble("pre").with_epilogue("post")
+                } else {
+                    m
+                })
+            };
+            let m = form()?;
+            let first = m.boundary_delimiter(Delimiter::First);
+            let middle = m.boundary_delimiter(Delimiter::Middle);
+            let last = m.boundary_delimiter(Delimiter::Last);
+            let size_hint = m.size_hint().unwrap();
+            let ranges = m.byte_ranges().unwrap();
+
+            let bytes = m.into_bytes().await?;
+            let mut streamed = Vec::new();
+            let mut stream = Box::pin(form()?.into_stream(Some(3)));
+            while let Some(chunk) = stream.next().await {
+                streamed.extend(chunk?);
+            }
+            let mut read = Vec::new();
+            form()?.into_reader(Some(3)).read_to_end(&mut read).await?;
+
+            assert_eq!(bytes, streamed);
+            assert_eq!(bytes, read);
+            assert_eq!(bytes.len(), size_hint);
+            assert!(bytes.starts_with(&first));
+            assert!(bytes.ends_with(&last));
+            for range in &ranges[1..] {
+                assert_eq!(
+                    &bytes[range.header.start - middle.len()..range.header.start],
+                    middle
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_urlencoded() -> Result<()> {
+        let mut m = Multipart::from_bytes_limited(b"--b--", "b", 0, 0)?;
+        m.add_urlencoded("form", [("a", "1"), ("b", "2")]);
+        m.add_urlencoded("escaped", [("q", "a b&c=d"), ("é", "100%")]);
+        let body = String::from_utf8(m.into_bytes().await?).unwrap();
+        assert!(body.contains(
+            "name=\"form\"\r\nContent-Type: application/x-www-form-urlencoded\r\n\r\na=1&b=2\r\n"
+        ));
+        assert!(body.contains("\r\n\r\nq=a+b%26c%3Dd&%C3%A9=100%25\r\n"));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_into_reader_partial_consume() -> Result<()> {
+        use futures_lite::AsyncBufReadExt;
+        let form = || -> Result<Multipart<'static>> {
+            let mut m = create_multipart();
+            let data = (0..=255).cycle().take(1000).collect::<Vec<u8>>();
+            m.add_file_bytes(
+                "b64",
+                "a.bin",
+                &data,
+                "application/octet-stream",
+                Some(Encoding::Base64),
+            )?;
+            m.add_enc_text("qp", "café \r\n".repeat(30), Encoding::QuotedPrintable);
+            Ok(m)
+        };
+        let expected = form()?.into_bytes().await?;
+
+        let mut reader = Box::pin(form()?.into_reader(Some(10)));
+        let mut output = Vec::new();
+        loop {
+            let available = reader.fill_buf().await?;
+            if available.is_empty() {
+                break;
+            }
+            // Consume a few bytes at a time, leaving the rest for the next fill
+            let amt = available.len().min(7);
+            output.extend_from_slice(&available[..amt]);
+            reader.as_mut().consume(amt);
+        }
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_total_buffer_budget() -> Result<()> {
+        let form = |budget: Option<usize>| -> Result<Multipart<'static>> {
+            let mut m = create_multipart();
+            if let Some(budget) = budget {
+                m = m.with_total_buffer_budget(budget);
+            }
+            for i in 0..4 {
+                let data = futures_lite::io::Cursor::new(vec![b'a' + i; 10_000]);
+                m.add_async_read(
+                    format!("file{}", i),
+                    "a.txt",
+                    "text/plain",
+                    None,
+                    data,
+                    Some(10_000),
+                )?;
+            }
+            Ok(m)
+        };
+
+        // Each of the four reader-backed parts gets a quarter of the budget
+        let budgeted = form(Some(1000))?;
+        assert_eq!(budgeted.part_buf_sizes(None)[2..], [Some(250); 4]);
+        assert_eq!(budgeted.part_buf_sizes(Some(100))[2..], [Some(100); 4]);
+        // Small in-memory parts keep their own, smaller size
+        assert_eq!(budgeted.part_buf_sizes(None)[0], Some(6));
+        assert_eq!(form(None)?.part_buf_sizes(None)[2..], [Some(4096); 4]);
+
+        let (stream, log) = budgeted.into_stream_debug(None);
+        let mut bytes = Vec::new();
+        let mut stream = Box::pin(stream);
+        while let Some(chunk) = stream.next().await {
+            bytes.extend(chunk?);
+        }
+        assert_eq!(bytes, form(None)?.into_bytes().await?);
+        let log = log.lock().unwrap().clone();
+        let file_chunks = log
+            .iter()
+            .filter(|chunk| chunk.kind == ChunkKind::Body)
+            .filter(|chunk| {
+                chunk
+                    .field_name
+                    .as_deref()
+                    .is_some_and(|name| name.starts_with("file"))
+            });
+        assert!(file_chunks.map(|chunk| chunk.len).all(|len| len <= 250));
+
+        let mut read = Vec::new();
+        form(Some(1000))?
+            .into_reader(None)
+            .read_to_end(&mut read)
+            .await?;
+        assert_eq!(read, bytes);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_transfer_profile() -> Result<()> {
+        let mut m = Multipart::new().with_profile(TransferProfile::Email7bit);
+        m.add_text("note", "café");
+        m.add_file_bytes("image", "a.png", [0x89, b'P', 0], "image/png", None)?;
+        m.add_file_bytes(
+            "raw",
+            "raw.bin",
+            [1, 2],
+            "application/octet-stream",
+            Some(Encoding::EightBit),
+        )?;
+        let encodings = m
+            .fields
+            .iter()
+            .map(|part| part.encoding())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            encodings,
+            [
+                Some(Encoding::QuotedPrintable),
+                Some(Encoding::Base64),
+                Some(Encoding::EightBit)
+            ]
+        );
+        let body = String::from_utf8(m.into_bytes().await?).unwrap();
+        assert!(body.contains("Content-Transfer-Encoding: quoted-printable\r\n\r\ncaf=C3=A9\r\n"));
+        assert!(body.contains("Content-Transfer-Encoding: base64\r\n\r\niVAA"));
+
+        let mut m = Multipart::new().with_profile(TransferProfile::Email8bit);
+        m.add_text("note", "café");
+        m.add_file_bytes("image", "a.png", [0x89, b'P', 0], "image/png", None)?;
+        let encodings = m
+            .fields
+            .iter()
+            .map(|part| part.encoding())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            encodings,
+            [Some(Encoding::EightBit), Some(Encoding::Base64)]
+        );
+
+        let mut m = Multipart::new();
+        m.add_text("note", "café");
+        m.add_file_bytes("image", "a.png", [0x89, b'P', 0], "image/png", None)?;
+        assert!(m.fields.iter().all(|part| part.encoding().is_none()));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_transfer_profile_applies_to_readers() -> Result<()> {
+        let data = [0xff, 0xfe, b'\n', 0x80];
+        let mut m = create_multipart().with_profile(TransferProfile::Email7bit);
+        m.add_async_read(
+            "reader",
+            "a.bin",
+            "application/octet-stream",
+            None,
+            futures_lite::io::Cursor::new(data.to_vec()),
+            Some(data.len()),
+        )?;
+        m.add_open_file(
+            "file",
+            "b.bin",
+            futures_lite::io::Cursor::new(data.to_vec()),
+            Some(data.len()),
+            None,
+        );
+        assert_eq!(m.fields[2].encoding(), Some(Encoding::Base64));
+        assert_eq!(m.fields[3].encoding(), Some(Encoding::Base64));
+
+        let bytes = m.into_bytes().await?;
+        assert!(bytes.is_ascii());
+        let body = String::from_utf8(bytes).unwrap();
+        assert_eq!(
+            body.matches("Content-Transfer-Encoding: base64\r\n\r\n//4KgA==\r\n")
+                .count(),
+            2
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_take_field_stream() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_file("file", "Cargo.toml", Some(Encoding::Base64))
+            .await?;
+        assert!(m.take_field_stream("missing").is_none());
+
+        let mut stream = Box::pin(m.take_field_stream("file").unwrap());
+        let mut body = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            body.extend(chunk?);
+        }
+        assert_eq!(body, std::fs::read("Cargo.toml")?);
+        assert_eq!(m.len(), 2);
+        assert!(m.take_field_stream("file").is_none());
+
+        let mut stream = Box::pin(m.take_field_stream("field1").unwrap());
+        assert_eq!(stream.next().await.transpose()?, Some(b"value1".to_vec()));
+        assert_eq!(m.text_fields(), [("field2", "value2".into())]);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_base64() -> Result<()> {
+        let mut m = Multipart::new().with_normalize_text_crlf();
+        m.boundary = "test-boundary".into();
+        m.add_base64("token", "line\nsecret");
+        m.add_base64("bytes", [0, 159, 255]);
+
+        let bytes = m.into_bytes().await?;
+        let body = String::from_utf8(bytes.clone()).unwrap();
+        assert!(body.contains(
+            "Content-Type: text/plain\r\nContent-Transfer-Encoding: base64\r\n\r\nbGluZQpzZWNyZXQ=\r\n"
+        ));
+
+        let parsed = Multipart::from_bytes_limited(&bytes, "test-boundary", 2, 100)?;
+        assert_eq!(parsed.fields[0].data.as_bytes(), Some(&b"line\nsecret"[..]));
+        assert_eq!(parsed.fields[1].data.as_bytes(), Some(&[0, 159, 255][..]));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_total_parts_counts_nested_forms() -> Result<()> {
+        let mut inner = Multipart::mixed().with_boundary("inner-boundary")?;
+        inner.add_attachment("a.txt", "first", "text/plain", None)?;
+        inner.add_attachment("b.txt", "second", "text/plain", None)?;
+        let inner = inner.into_bytes().await?;
+
+        let mut m = create_multipart();
+        m.add_binary_field(
+            "nested",
+            &inner,
+            "multipart/mixed; boundary=inner-boundary",
+            None,
+        )?;
+        assert_eq!(m.len(), 3);
+        assert_eq!(m.total_parts(), 5);
+
+        // Nesting is followed recursively
+        let mut middle = Multipart::mixed().with_boundary("middle-boundary")?;
+        middle.add_binary_field(
+            "nested",
+            &inner,
+            "multipart/mixed; boundary=inner-boundary",
+            None,
+        )?;
+        m.add_binary_field(
+            "deeper",
+            middle.into_bytes().await?,
+            "multipart/mixed; boundary=middle-boundary",
+            None,
+        )?;
+        assert_eq!(m.total_parts(), 9);
+
+        // A body that isn't framed by its boundary counts as one part
+        m.add_binary_field(
+            "broken",
+            "not multipart",
+            "multipart/mixed; boundary=x",
+            None,
+        )?;
+        assert_eq!(m.total_parts(), 10);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_total_parts_counts_nameless_nested_parts() -> Result<()> {
+        let related = b"--related\r\n\
+            Content-Type: text/html\r\n\
+            Content-ID: <page>\r\n\r\n\
+            <img src=\"cid:logo\">\r\n\
+            --related\r\n\
+            Content-Type: image/png\r\n\
+            Content-ID: <logo>\r\n\
+            Content-Transfer-Encoding: base64\r\n\r\n\
+            iVBORw==\r\n\
+            --related\r\n\
+            Content-ID: <notes>\r\n\r\n\
+            plain\r\n\
+            --related--\r\n";
+        let mut m = create_multipart();
+        m.add_binary_field(
+            "page",
+            related,
+            "multipart/related; boundary=related; type=\"text/html\"",
+            None,
+        )?;
+        assert_eq!(m.total_parts(), 6);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_into_chunks() -> Result<()> {
+        let form = || async {
+            let mut m = create_multipart();
+            m.add_file("file", "Cargo.toml", None).await?;
+            Result::Ok(m)
+        };
+        let expected = form().await?.into_bytes().await?;
+
+        let chunks = form().await?.into_chunks(Some(64)).await?;
+        assert_eq!(chunks.concat(), expected);
+        // Chunk boundaries are the same as the stream's
+        let mut stream = form().await?.into_stream(Some(64));
+        let mut streamed = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            streamed.push(chunk?);
+        }
+        assert_eq!(chunks, streamed);
+        assert!(chunks.len() > expected.len() / 64);
+
+        assert!(Multipart::new().into_chunks(None).await?.is_empty());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_part_header() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_file_bytes(
+            "logo",
+            "logo.png",
+            [1, 2, 3],
+            "image/png",
+            Some(Encoding::Base64),
+        )?;
+        m.add_part_header(2, "Content-ID", "<abc>")?;
+        m.add_part_header(2, "X-Custom", "value")?;
+        m.add_part_header(0, "X-Field", "first")?;
+        assert!(m.add_part_header(3, "X-Custom", "value").is_err());
+        assert!(m.add_part_header(0, "content-type", "text/html").is_err());
+        assert!(m.add_part_header(0, "X Bad", "value").is_err());
+        assert!(m
+            .add_part_header(0, "X-Injected", "a\r\nX-Other: b")
+            .is_err());
+
+        let size = m.content_length();
+        let bytes = m.into_bytes().await?;
+        assert_eq!(size, Some(bytes.len() as u64));
+        let body = String::from_utf8(bytes).unwrap();
+        assert!(body.contains(
+            "Content-Type: image/png\r\nContent-Transfer-Encoding: base64\r\nContent-ID: <abc>\r\nX-Custom: value\r\n\r\nAQID\r\n"
+        ));
+        assert!(body.contains("Content-Type: text/plain\r\nX-Field: first\r\n\r\nvalue1\r\n"));
+
+        let mut m = create_multipart();
+        m.append_raw(b"Content-Type: text/plain\r\n\r\nraw".to_vec());
+        assert!(m.add_part_header(2, "X-Custom", "value").is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_apply_to_reuses_form() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_file_bytes(
+            "file",
+            "a.txt",
+            "contents",
+            "text/plain",
+            Some(Encoding::Base64),
+        )?;
+        m.add_part_header(2, "Content-ID", "<file>")?;
+
+        let mut first = Request::new(Method::Post, Url::parse("http://example.com/a")?);
+        let mut second = Request::new(Method::Put, Url::parse("http://example.org/b")?);
+        m.apply_to(&mut first)?;
+        m.apply_to(&mut second)?;
+        for req in [&first, &second] {
+            assert_eq!(
+                req.header("Content-Type").unwrap().as_str(),
+                "multipart/form-data; boundary=test-boundary"
+            );
+        }
+        let body = first.body_bytes().await?;
+        assert_eq!(body, second.body_bytes().await?);
+        assert_eq!(body, m.into_bytes().await?);
+
+        let mut m = create_multipart();
+        m.add_file("file", "Cargo.toml", None).await?;
+        assert!(m.apply_to(&mut first).is_err());
         Ok(())
     }
 
-    /// Adds a file field to the form wrapping a sync reader.
-    pub fn add_sync_read(
-        &mut self,
-        name: impl Into<Cow<'m, str>>,
-        filename: impl Into<Cow<'m, str>>,
-        content_type: &str,
-        encoding: Option<Encoding>,
-        mut data: impl Read + Seek + Send + 'static,
-    ) -> Result<()> {
-        let mut buffer = Vec::new();
-        data.read_to_end(&mut buffer)?;
-        let body = Body::from(buffer);
-        self.fields.push(Part::file_raw(
-            name,
-            filename,
-            content_type.parse()?,
-            encoding,
-            body,
-        ));
+    #[async_std::test]
+    async fn test_quotes_and_line_breaks_are_escaped_in_dispositions() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_text("foo\"bar", "value");
+        m.add_file_bytes("file", "a\r\nX-Injected: 1.txt", "data", "text/plain", None)?;
+        let disposition =
+            ContentDisposition::form_data("param").with_param("note", "x\r\nX-Injected: 2");
+        let data = futures_lite::io::Cursor::new(b"reader".to_vec());
+        m.add_reader_with_content_disposition(
+            disposition,
+            mime_guess::mime::TEXT_PLAIN,
+            None,
+            data,
+            Some(6),
+        )?;
+
+        let bytes = m.into_bytes().await?;
+        let body = String::from_utf8(bytes.clone()).unwrap();
+        assert!(body.contains("Content-Disposition: form-data; name=\"foo%22bar\"\r\n"));
+        assert!(body.contains("; filename=\"a%0D%0AX-Injected: 1.txt\"\r\n"));
+        assert!(body.contains("; note=\"x%0D%0AX-Injected: 2\"\r\n"));
+        assert!(!body.contains("\nX-Injected"));
+
+        // The framing is intact: every part parses back with its value
+        let parsed = Multipart::from_bytes_limited(&bytes, "test-boundary", 5, 100)?;
+        let values = parsed
+            .fields
+            .iter()
+            .map(|part| part.data.as_bytes().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [&b"value1"[..], b"value2", b"value", b"data", b"reader"]
+        );
+
+        // The ASCII fallback of a non-ASCII attachment filename is escaped too
+        let mut m = Multipart::mixed();
+        m.add_attachment(
+            "r\u{e9}sum\u{e9} \"final\".pdf",
+            "pdf",
+            "application/pdf",
+            None,
+        )?;
+        assert_eq!(
+            m.content_dispositions()[0].as_deref(),
+            Some("attachment; filename=\"r_sum_ %22final%22.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9%20%22final%22.pdf")
+        );
         Ok(())
     }
 
-    /// Sets the request body to the multipart form data.
-    pub fn set_request(self, req: &mut Request) {
-        let content_type = format!("multipart/form-data; boundary={}", &self.boundary);
-        req.insert_header("Content-Type", content_type);
+    #[async_std::test]
+    async fn test_trailing_backslash_is_escaped_in_dispositions() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_text("field\\", "value");
+        m.add_file_bytes("file", "C:\\dir\\", "data", "text/plain", None)?;
 
-        if let Some(size) = self.size_hint() {
-            req.insert_header("Content-Length", size.to_string());
-        } else {
-            // If size not known, unset Content-Length header
-            req.remove_header("Content-Length");
+        let bytes = m.into_bytes().await?;
+        let body = String::from_utf8(bytes.clone()).unwrap();
+        // A trailing backslash would otherwise escape the closing quote
+        assert!(body.contains("Content-Disposition: form-data; name=\"field%5C\"\r\n"));
+        assert!(body.contains("; filename=\"C:%5Cdir%5C\"\r\n"));
+
+        let parsed = Multipart::from_bytes_limited(&bytes, "test-boundary", 4, 100)?;
+        let fields = parsed
+            .fields
+            .iter()
+            .map(|part| (part.name(), part.filename(), part.data.as_bytes().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            [
+                (Some("field1"), None, &b"value1"[..]),
+                (Some("field2"), None, b"value2"),
+                (Some("field%5C"), None, b"value"),
+                (Some("file"), Some("C:%5Cdir%5C"), b"data"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_url_safe_base64_round_trip() -> Result<()> {
+        // Encodes to `-_-__g`, which the standard alphabet can't decode
+        let data = [0xfb, 0xff, 0xbf, 0xfe];
+        let mut m = create_multipart();
+        for (name, encoding) in [
+            ("url_safe", Encoding::Base64UrlSafe),
+            ("padded", Encoding::Base64UrlSafePadded),
+        ] {
+            m.add_file_bytes(
+                name,
+                "a.bin",
+                data,
+                "application/octet-stream",
+                Some(encoding),
+            )?;
         }
+        let bytes = m.into_bytes().await?;
+        assert!(String::from_utf8_lossy(&bytes).contains("\r\n\r\n-_-__g\r\n"));
 
-        let body = self.into_body(None);
-        req.set_body(body);
+        let parsed = Multipart::from_bytes_limited(&bytes, "test-boundary", 4, 100)?;
+        let files = parsed
+            .fields
+            .iter()
+            .filter(|part| part.filename().is_some())
+            .map(|part| (part.name(), part.data.as_bytes()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            [
+                (Some("url_safe"), Some(&data[..])),
+                (Some("padded"), Some(&data[..]))
+            ]
+        );
+        Ok(())
     }
 
-    pub async fn set_request_bytes(self, req: &mut Request) -> Result<()> {
-        let content_type = format!("multipart/form-data; boundary={}", &self.boundary);
-        req.insert_header("Content-Type", content_type);
+    #[async_std::test]
+    async fn test_split_at_size() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_text("field3", "x".repeat(100));
+        m.add_file_bytes(
+            "file",
+            "a.bin",
+            vec![7u8; 150],
+            "application/octet-stream",
+            None,
+        )?;
+        m.add_enc_text("field5", "y".repeat(60), Encoding::Base64);
+        let names = m
+            .fields
+            .iter()
+            .map(|f| f.name().unwrap().to_string())
+            .collect::<Vec<_>>();
+        let max_bytes = 350;
 
-        if let Some(size) = self.size_hint() {
-            req.insert_header("Content-Length", size.to_string());
-        } else {
-            // If size not known, unset Content-Length header
-            req.remove_header("Content-Length");
+        let forms = m.split_at_size(max_bytes)?;
+        assert!(forms.len() > 1);
+        let mut split_names = Vec::new();
+        for form in forms {
+            assert_eq!(form.boundary, "test-boundary");
+            split_names.extend(form.fields.iter().map(|f| f.name().unwrap().to_string()));
+            let len = form.content_length().unwrap();
+            let bytes = form.into_bytes().await?;
+            assert_eq!(bytes.len() as u64, len);
+            assert!(len <= max_bytes, "{len} > {max_bytes}");
         }
+        assert_eq!(split_names, names);
 
-        let body = self.into_body_bytes().await?;
-        req.set_body(body);
+        // A single part larger than the cap can't be split
+        let mut m = create_multipart();
+        m.add_text("big", "z".repeat(1000));
+        assert!(m.split_at_size(max_bytes).is_err());
+        assert!(Multipart::new().split_at_size(10)?.is_empty());
         Ok(())
     }
 
-    /// Converts the multipart form to a `Body`.
-    pub async fn into_bytes(self) -> Result<Vec<u8>> {
-        let mut data: Vec<u8> = Vec::new();
+    #[async_std::test]
+    async fn test_set_request_exact() -> Result<()> {
+        let build = || {
+            let mut m = create_multipart();
+            m.add_enc_text("encoded", "hello base64", Encoding::Base64);
+            m.add_enc_text("qp", "caf\u{e9} = caf\u{e9}", Encoding::QuotedPrintable);
+            m
+        };
+        // Quoted-printable parts have no length up front
+        assert_eq!(build().content_length(), None);
+        let expected = build().into_bytes().await?;
 
-        for field in self.fields {
-            // Add boundary for each field
-            data.extend(format!("--{}\r\n", self.boundary).into_bytes());
-            field.extend(&mut data).await?;
-        }
-        data.extend_from_slice(b"\r\n");
+        let mut req = Request::new(Method::Post, Url::parse("http://example.com")?);
+        // Left over from an earlier body
+        req.insert_header("Content-Length", "1");
+        build().set_request_exact(&mut req).await?;
+        assert_eq!(req["Content-Length"], expected.len().to_string().as_str());
+        assert_eq!(req["Content-Type"], build().content_type().as_str());
+        assert_eq!(req.body_bytes().await?, expected);
+        Ok(())
+    }
 
-        // Add closing boundary
-        data.extend(format!("--{}--\r\n", self.boundary).into_bytes());
+    #[test]
+    fn test_with_max_text_len() -> Result<()> {
+        let mut m = create_multipart().with_max_text_len(5);
+        m.try_add_text("short", "12345")?;
+        assert!(m.try_add_text("long", "123456").is_err());
+        assert!(m.insert_text(0, "long", "123456").is_err());
+        assert!(m.add_text_mime("long", "123456", "text/plain").is_err());
+        assert_eq!(m.len(), 3);
+        // Binary values aren't text
+        m.add_binary_field("bin", [0u8; 6], "application/octet-stream", None)?;
+        m.add_base64("bin", [0u8; 6]);
+        assert_eq!(m.len(), 5);
 
-        Ok(data)
+        // Unlimited by default
+        let mut m = create_multipart();
+        m.try_add_text("long", "x".repeat(1 << 20))?;
+        Ok(())
     }
 
-    pub fn into_stream(self, buf_size: Option<usize>) -> impl Stream<Item = StreamChunk> {
-        if self.fields.is_empty() {
-            let empty_stream: Pin<Box<dyn Stream<Item = StreamChunk>>> =
-                Box::pin(futures_lite::stream::empty());
-            return empty_stream;
+    #[test]
+    fn test_with_max_text_len_panics_in_infallible_methods() {
+        let adders: [fn(&mut Multipart<'_>); 3] = [
+            |m| m.add_text("long", "123456"),
+            |m| m.add_enc_text("long", "123456", Encoding::QuotedPrintable),
+            |m| m.prepend_text("long", "123456"),
+        ];
+        for add in adders {
+            let mut m = create_multipart().with_max_text_len(5);
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| add(&mut m)));
+            assert!(result.is_err());
+            assert_eq!(m.len(), 2);
         }
+    }
 
-        let head_bytes = format!("--{}\r\n", self.boundary).into_bytes();
-        let head_stream = futures_lite::stream::once(Ok(head_bytes.clone()));
-        let seperator = format!("\r\n--{}\r\n", self.boundary).into_bytes();
-        let mut field_iter = self.fields.into_iter();
-        let start = field_iter.next().unwrap().into_stream(buf_size);
-        let start = Box::pin(head_stream.chain(start)) as Pin<Box<dyn Stream<Item = StreamChunk>>>;
-        let stream = field_iter.fold(start, |acc, field| {
-            let seperator = futures_lite::stream::once(Ok(seperator.clone()));
-            let stream = field.into_stream(buf_size);
-            Box::pin(acc.chain(seperator).chain(stream)) as Pin<Box<dyn Stream<Item = StreamChunk>>>
-        });
-        let tail = format!("\r\n--{}--\r\n", self.boundary).into_bytes();
-        let end = futures_lite::stream::once(Ok(tail));
-        Box::pin(stream.chain(end)) as Pin<Box<dyn Stream<Item = StreamChunk>>>
+    #[cfg(target_os = "linux")]
+    #[async_std::test]
+    async fn test_drop_closes_files() -> Result<()> {
+        let open_fds = || std::fs::read_dir("/proc/self/fd").unwrap().count();
+        // Other tests open files concurrently, so only large differences are meaningful
+        let files = 200;
+        let before = open_fds();
+
+        let mut m = Multipart::new();
+        for _ in 0..files {
+            m.add_file("file", "Cargo.toml", None).await?;
+        }
+        assert!(open_fds() >= before + files * 3 / 4);
+        drop(m);
+        assert!(open_fds() < before + files / 4);
+
+        // A partially sent form also releases its files
+        let mut m = Multipart::new();
+        for _ in 0..files {
+            m.add_file("file", "Cargo.toml", None).await?;
+        }
+        let mut stream = Box::pin(m.into_stream(Some(16)));
+        stream.next().await.transpose()?;
+        stream.next().await.transpose()?;
+        drop(stream);
+        assert!(open_fds() < before + files / 4);
+        Ok(())
     }
 
-    pub fn into_reader(self, buf_size: Option<usize>) -> impl AsyncBufRead + Send + Sync {
-        if self.fields.is_empty() {
-            return Box::pin(futures_lite::io::empty()) as Pin<Box<dyn AsyncBufRead + Send + Sync>>;
+    #[async_std::test]
+    async fn test_from_content_type() -> Result<()> {
+        let m = Multipart::from_content_type("multipart/form-data; boundary=abc123")?;
+        assert_eq!(m.boundary, "abc123");
+        assert_eq!(m.subtype, "form-data");
+        assert!(m.is_empty());
+
+        let mut m = Multipart::from_content_type(
+            "Multipart/Mixed; charset=utf-8; BOUNDARY=\"gc0p4Jq0M:2Yt08j\"",
+        )?;
+        assert_eq!(m.boundary, "gc0p4Jq0M:2Yt08j");
+        assert_eq!(m.subtype, "mixed");
+        m.add_text("field", "value");
+        let body = m.into_bytes().await?;
+        assert!(body.starts_with(b"--gc0p4Jq0M:2Yt08j\r\n"));
+
+        for value in [
+            "text/plain; boundary=abc",
+            "multipart/form-data",
+            "multipart/form-data; boundary=\"trailing space \"",
+            "multipart/form-data; boundary=\"\"",
+        ] {
+            assert!(Multipart::from_content_type(value).is_err(), "{value}");
         }
+        Ok(())
+    }
 
-        let head_bytes = format!("--{}\r\n", self.boundary).into_bytes();
-        let header_reader = futures_lite::io::Cursor::new(head_bytes.clone());
-        let seperator = format!("\r\n--{}\r\n", self.boundary).into_bytes();
+    #[test]
+    fn test_boundary_prefix_with_tspecial() -> Result<()> {
+        let m = Multipart::new().with_boundary_prefix("a=b")?;
+        assert!(m.boundary.starts_with("a=b"));
+        let content_type = m.content_type();
+        assert_eq!(
+            content_type,
+            format!("multipart/form-data; boundary=\"{}\"", m.boundary)
+        );
+        let parsed = Multipart::from_content_type(&content_type)?;
+        assert_eq!(parsed.boundary, m.boundary);
 
-        let mut field_iter = self.fields.into_iter();
-        let start = field_iter.next().unwrap().into_reader(buf_size);
-        let start =
-            Box::pin(header_reader.chain(start)) as Pin<Box<dyn AsyncBufRead + Send + Sync>>;
-        let reader = field_iter.fold(start, |acc, field| {
-            let seperator = futures_lite::io::Cursor::new(seperator.clone());
-            let reader = field.into_reader(buf_size);
-            Box::pin(acc.chain(seperator).chain(reader)) as Pin<Box<dyn AsyncBufRead + Send + Sync>>
-        });
-        let tail = format!("\r\n--{}--\r\n", self.boundary).into_bytes();
-        let end = futures_lite::io::Cursor::new(tail);
-        Box::pin(reader.chain(end)) as Pin<Box<dyn AsyncBufRead + Send + Sync>>
+        let m = Multipart::new().with_boundary("x(1)/y:z?")?;
+        assert_eq!(
+            m.content_type(),
+            "multipart/form-data; boundary=\"x(1)/y:z?\""
+        );
+        Ok(())
     }
 
-    fn size_hint(&self) -> Option<usize> {
-        // The first seperator is 30 + 2 + 2 = 34 bytes
-        // The last seperator is 30 + 2 + 2 + 2 + 2 = 38 bytes
-        // The seperator between fields is 30 + 2 + 2 + 2 = 36 bytes
-        // The total size is 34 + 36 * (n - 1) + 38 = 36 * n + 2
+    #[test]
+    fn test_content_type_quotes_boundary() -> Result<()> {
+        let m = Multipart::from_content_type("multipart/form-data; boundary=\"simple boundary\"")?;
+        assert_eq!(m.boundary, "simple boundary");
+        let content_type = m.content_type();
+        assert_eq!(
+            content_type,
+            "multipart/form-data; boundary=\"simple boundary\""
+        );
+        assert_eq!(
+            Multipart::from_content_type(&content_type)?.boundary,
+            "simple boundary"
+        );
+        Ok(())
+    }
 
-        let mut size = 34;
-        for field in &self.fields {
-            size += 36;
-            size += field.size_hint()?;
+    #[async_std::test]
+    async fn test_into_stream_debug() -> Result<()> {
+        let m = create_multipart();
+        let expected = create_multipart().into_bytes().await?;
+        let header_len = m.fields[0].header_len();
+
+        let (stream, log) = m.into_stream_debug(None);
+        let mut output = Vec::new();
+        let mut stream = Box::pin(stream);
+        while let Some(chunk) = stream.next().await {
+            output.extend(chunk?);
+        }
+        assert_eq!(output, expected);
+
+        let log = log.lock().unwrap();
+        let structure = log
+            .iter()
+            .map(|info| (info.kind, info.field_name.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            structure,
+            [
+                (ChunkKind::Boundary, None),
+                (ChunkKind::Header, Some("field1")),
+                (ChunkKind::Body, Some("field1")),
+                (ChunkKind::Boundary, None),
+                (ChunkKind::Header, Some("field2")),
+                (ChunkKind::Body, Some("field2")),
+                (ChunkKind::Boundary, None),
+            ]
+        );
+        assert_eq!(log[1].len, header_len);
+        // Offsets are contiguous and cover the whole output
+        let mut offset = 0;
+        for info in log.iter() {
+            assert_eq!(info.offset, offset);
+            offset += info.len as u64;
         }
-        Some(size + 38)
+        assert_eq!(offset, output.len() as u64);
+        assert_eq!(&output[log[2].offset as usize..][..log[2].len], b"value1");
+        Ok(())
     }
 
-    fn into_body(self, buf_size: Option<usize>) -> Body {
-        let hint = self.size_hint();
-        Body::from_reader(self.into_reader(buf_size), hint)
+    #[async_std::test]
+    async fn test_with_normalize_text_crlf() -> Result<()> {
+        let mut m = Multipart::from_bytes_limited(b"--b--", "b", 0, 0)?.with_normalize_text_crlf();
+        m.add_text("mixed", "a\nb\r\nc\rd\n\n");
+        m.add_file_bytes("file", "a.txt", b"x\ny", "text/plain", None)?;
+        let body = String::from_utf8(m.into_bytes().await?).unwrap();
+        assert!(body.contains("\r\n\r\na\r\nb\r\nc\r\nd\r\n\r\n\r\n--b\r\n"));
+        // File bodies are untouched
+        assert!(body.contains("\r\n\r\nx\ny\r\n--b--"));
+        Ok(())
     }
 
-    async fn into_body_bytes(self) -> Result<Body> {
-        let bytes = self.into_bytes().await?;
-        Ok(Body::from(bytes))
+    #[async_std::test]
+    async fn test_normalize_text_crlf_skips_binary_fields() -> Result<()> {
+        let data = [0, b'\n', 1, b'\r', 2, b'\r', b'\n'];
+        let mut m = create_multipart().with_normalize_text_crlf();
+        m.add_binary_field("bin", data, "application/octet-stream", None)?;
+        m.add_text_typed("csv", "a\nb", "text/csv".parse()?, None, None)?;
+        assert_eq!(m.fields[2].data.as_bytes(), Some(&data[..]));
+        assert_eq!(m.fields[3].data.as_bytes(), Some(&b"a\r\nb"[..]));
+
+        let bytes = m.into_bytes().await?;
+        let mut expected = b"Content-Type: application/octet-stream\r\n\r\n".to_vec();
+        expected.extend_from_slice(&data);
+        expected.extend_from_slice(b"\r\n--test-boundary\r\n");
+        assert!(bytes
+            .windows(expected.len())
+            .any(|window| window == expected));
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[async_std::test]
+    async fn test_content_dispositions() -> Result<()> {
+        let mut m = Multipart::mixed();
+        m.add_attachment("résumé; final.pdf", b"%PDF", "application/pdf", None)?;
+        m.add_text("note", "hi");
+        let dispositions = m.content_dispositions();
+        assert_eq!(
+            dispositions[0].as_deref(),
+            Some("attachment; filename=\"r_sum_; final.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9%3B%20final.pdf")
+        );
+        assert_eq!(dispositions[1].as_deref(), Some("form-data; name=\"note\""));
 
-    // Helper to create a Multipart with fixed boundary and sample text fields.
-    fn create_multipart<'a>() -> Multipart<'a> {
-        let mut m = Multipart::new();
-        // Override the randomly generated boundary for consistency in tests.
-        m.boundary = "test-boundary".into();
-        m.add_text("field1", "value1");
-        m.add_text("field2", "value2");
-        m
+        let body = String::from_utf8(m.into_bytes().await?).unwrap();
+        for disposition in dispositions.into_iter().flatten() {
+            assert!(body.contains(&format!("\r\nContent-Disposition: {}\r\n", disposition)));
+        }
+        Ok(())
     }
 
     #[async_std::test]
@@ -287,6 +3953,359 @@ mod tests {
         Ok(())
     }
 
+    #[async_std::test]
+    async fn test_base64_file_same_on_all_paths() -> Result<()> {
+        let form = || async {
+            let mut m = create_multipart();
+            m.add_file("file", "Cargo.toml", Some(Encoding::Base64))
+                .await?;
+            // Four bytes end in a partial base64 group
+            m.add_file_bytes(
+                "bytes",
+                "b.bin",
+                [1, 2, 3, 4],
+                "application/octet-stream",
+                Some(Encoding::Base64),
+            )?;
+            Result::Ok(m)
+        };
+
+        let bytes = form().await?.into_bytes().await?;
+        for buf_size in [Some(1), Some(8), None] {
+            let mut stream = form().await?.into_stream(buf_size);
+            let mut stream_output = Vec::new();
+            while let Some(chunk) = stream.next().await {
+                stream_output.extend(chunk?);
+            }
+            assert_eq!(stream_output, bytes);
+
+            let mut reader_output = Vec::new();
+            form()
+                .await?
+                .into_reader(buf_size)
+                .read_to_end(&mut reader_output)
+                .await?;
+            assert_eq!(reader_output, bytes);
+        }
+        let encoded = crate::encode_all(&std::fs::read("Cargo.toml")?, Encoding::Base64);
+        assert!(bytes.windows(encoded.len()).any(|window| window == encoded));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_stream_yields_part_header_as_own_chunk() -> Result<()> {
+        let m = create_multipart();
+        let header = m.fields[0].header_bytes();
+
+        let mut stream = m.into_stream(Some(8));
+        let boundary = stream.next().await.unwrap()?;
+        assert_eq!(boundary, b"--test-boundary\r\n");
+
+        let first = stream.next().await.unwrap()?;
+        assert_eq!(first, header);
+
+        let body = stream.next().await.unwrap()?;
+        assert_eq!(body, b"value1");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_from_request_round_trip() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_file_bytes("file", "file.txt", "Hello, world!", "text/plain", None)?;
+        let mut req = Request::new(Method::Post, Url::parse("http://example.com")?);
+        m.set_request(&mut req);
+
+        let parsed = Multipart::from_request(&mut req).await?;
+        assert_eq!(parsed.boundary, "test-boundary");
+        let names: Vec<_> = parsed.fields.iter().filter_map(|f| f.name()).collect();
+        assert_eq!(names, ["field1", "field2", "file"]);
+        assert_eq!(parsed.fields[2].filename(), Some("file.txt"));
+
+        let mut expected = create_multipart();
+        expected.add_file_bytes("file", "file.txt", "Hello, world!", "text/plain", None)?;
+        assert_eq!(parsed.into_bytes().await?, expected.into_bytes().await?);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_from_request_rejects_invalid_boundary() -> Result<()> {
+        for boundary in [
+            "x".repeat(71),
+            "\"a@b\"".into(),
+            "\"trailing space \"".into(),
+        ] {
+            let body = format!(
+                "--{0}\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--{0}--\r\n",
+                boundary.trim_matches('"')
+            );
+            let mut req = Request::new(Method::Post, Url::parse("http://example.com")?);
+            req.insert_header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            );
+            req.set_body(body);
+            assert!(
+                Multipart::from_request(&mut req).await.is_err(),
+                "{boundary}"
+            );
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_mixed_attachment_without_name() -> Result<()> {
+        let mut m = Multipart::mixed();
+        m.boundary = "test-boundary".into();
+        m.add_attachment("notes.txt", "some notes", "text/plain", None)?;
+        assert_eq!(m.content_type(), "multipart/mixed; boundary=test-boundary");
+
+        let body = String::from_utf8(m.into_bytes().await?).unwrap();
+        assert!(body.contains("Content-Disposition: attachment; filename=\"notes.txt\"\r\n"));
+        assert!(!body.contains("; name="));
+
+        let mut form = Multipart::new();
+        assert!(form
+            .add_attachment("notes.txt", "some notes", "text/plain", None)
+            .is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_validate_boundary_in_body() -> Result<()> {
+        let mut m = create_multipart();
+        m.validate_boundary_in_body().await?;
+
+        m.add_text("field3", "before --test-boundary after");
+        let err = m.validate_boundary_in_body().await.unwrap_err();
+        assert!(err.to_string().contains("`field3`"));
+        assert_eq!(m.boundary, "test-boundary");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_stream_len_base64() -> Result<()> {
+        let build = || -> Result<Multipart<'static>> {
+            let mut m = create_multipart();
+            m.add_file_bytes(
+                "file",
+                "file.bin",
+                [7u8; 100],
+                "application/octet-stream",
+                Some(Encoding::Base64),
+            )?;
+            m.add_enc_text("quoted", "caf\u{e9} = ok", Encoding::QuotedPrintable);
+            Ok(m)
+        };
+
+        let len = build()?.stream_len().await?;
+
+        let mut stream = build()?.into_stream(None);
+        let mut output = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            output.extend(chunk?);
+        }
+        assert_eq!(len, output.len());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_default_file_encoding() -> Result<()> {
+        let mut m = Multipart::new().with_default_file_encoding(Encoding::Base64);
+        m.boundary = "test-boundary".into();
+        m.add_text("field", "plain value");
+        m.add_file_bytes("file", "file.txt", "Hello, world!", "text/plain", None)?;
+        m.add_file_bytes(
+            "raw",
+            "raw.txt",
+            "raw value",
+            "text/plain",
+            Some(Encoding::EightBit),
+        )?;
+
+        let body = String::from_utf8(m.into_bytes().await?).unwrap();
+        assert!(body.contains("\r\n\r\nplain value\r\n"));
+        assert!(body.contains("Content-Transfer-Encoding: base64\r\n\r\nSGVsbG8sIHdvcmxkIQ==\r\n"));
+        assert!(body.contains("Content-Transfer-Encoding: 8bit\r\n\r\nraw value\r\n"));
+        assert_eq!(body.matches("Content-Transfer-Encoding").count(), 2);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_file_info() -> Result<()> {
+        let mut m = create_multipart();
+        let info = m
+            .add_file_info("file", "./examples/file.txt", Some(Encoding::Base64))
+            .await?;
+        assert_eq!(info.filename, "file.txt");
+        assert_eq!(info.mime, mime_guess::mime::TEXT_PLAIN);
+        assert_eq!(info.len, std::fs::metadata("./examples/file.txt")?.len());
+        assert_eq!(info.encoding, Some(Encoding::Base64));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_into_body_length_with_encodings() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_file_bytes(
+            "file",
+            "file.bin",
+            [1u8; 100],
+            "application/octet-stream",
+            Some(Encoding::Base64),
+        )?;
+        let body = m.into_body(None);
+        let len = body.len().unwrap();
+        assert_eq!(len, body.into_bytes().await?.len());
+
+        let mut m = create_multipart();
+        m.add_enc_text("quoted", "caf\u{e9}", Encoding::QuotedPrintable);
+        assert_eq!(m.into_body(None).len(), None);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_text_opt() -> Result<()> {
+        let mut m = Multipart::new();
+        assert!(!m.add_text_opt("missing", None::<&str>));
+        assert!(m.add_text_opt("present", Some("value")));
+        assert_eq!(m.fields.len(), 1);
+        assert_eq!(m.fields[0].name(), Some("present"));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_part_headers_reproduce_body() -> Result<()> {
+        let m = create_multipart();
+        let headers = m.part_headers();
+        assert_eq!(headers.len(), 2);
+
+        let mut expected = b"--test-boundary\r\n".to_vec();
+        expected.extend(&headers[0]);
+        expected.extend(b"value1\r\n--test-boundary\r\n");
+        expected.extend(&headers[1]);
+        expected.extend(b"value2\r\n--test-boundary--\r\n");
+        assert_eq!(m.into_bytes().await?, expected);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_byte_ranges() -> Result<()> {
+        let mut m = create_multipart();
+        m.add_file_bytes(
+            "file",
+            "file.txt",
+            "Hello, world!",
+            "text/plain",
+            Some(Encoding::Base64),
+        )?;
+        let ranges = m.byte_ranges().unwrap();
+        let headers = m.part_headers();
+        let bytes = m.into_bytes().await?;
+
+        assert_eq!(ranges.len(), 3);
+        for (range, header) in ranges.iter().zip(&headers) {
+            assert_eq!(&bytes[range.header.clone()], header.as_slice());
+        }
+        assert_eq!(&bytes[ranges[0].body.clone()], b"value1");
+        assert_eq!(&bytes[ranges[1].body.clone()], b"value2");
+        assert_eq!(&bytes[ranges[2].body.clone()], b"SGVsbG8sIHdvcmxkIQ==");
+        assert_eq!(&bytes[ranges[2].body.end..], b"\r\n--test-boundary--\r\n");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_body() -> Result<()> {
+        let content = b"forwarded body".to_vec();
+        let len = content.len();
+        let body = Body::from_reader(futures_lite::io::Cursor::new(content), Some(len));
+
+        let mut m = create_multipart();
+        m.add_body(
+            "forward",
+            "data.bin",
+            "application/octet-stream",
+            None,
+            body,
+        )?;
+        assert_eq!(m.fields[2].data.len(), Some(len));
+
+        let expected_size = m.size_hint().unwrap();
+        let bytes = m.into_bytes().await?;
+        assert_eq!(expected_size, bytes.len());
+        let output = String::from_utf8(bytes).unwrap();
+        assert!(output.contains("filename=\"data.bin\"\r\nContent-Type: application/octet-stream\r\n\r\nforwarded body\r\n"));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_add_binary_field() -> Result<()> {
+        let mut m = Multipart::new();
+        m.add_binary_field(
+            "blob",
+            [0u8, 159, 146, 150],
+            "application/octet-stream",
+            None,
+        )?;
+        let headers = String::from_utf8(m.part_headers().remove(0)).unwrap();
+        assert_eq!(
+            headers,
+            "Content-Disposition: form-data; name=\"blob\"\r\nContent-Type: application/octet-stream\r\n\r\n"
+        );
+        assert!(!headers.contains("filename="));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_append_raw() -> Result<()> {
+        let block = b"Content-Disposition: form-data; name=\"cached\"\r\n\r\ncached value".to_vec();
+        let mut m = create_multipart();
+        m.append_raw(block.clone());
+        let expected_size = m.size_hint().unwrap();
+
+        let bytes = m.into_bytes().await?;
+        assert_eq!(expected_size, bytes.len());
+        let mut expected = b"value2\r\n--test-boundary\r\n".to_vec();
+        expected.extend(&block);
+        expected.extend(b"\r\n--test-boundary--\r\n");
+        assert!(bytes.ends_with(&expected));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_content_type_params() -> Result<()> {
+        let mut m = Multipart::mixed().with_content_type_param("type", "application/json")?;
+        m.boundary = "test-boundary".into();
+        assert_eq!(
+            m.content_type(),
+            "multipart/mixed; boundary=test-boundary; type=\"application/json\""
+        );
+
+        assert!(Multipart::new()
+            .with_content_type_param("charset", "utf-8")
+            .is_err());
+        assert!(Multipart::new()
+            .with_content_type_param("Boundary", "other")
+            .is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_from_bytes_limited() -> Result<()> {
+        let bytes = create_multipart().into_bytes().await?;
+
+        let parsed = Multipart::from_bytes_limited(&bytes, "test-boundary", 2, 6)?;
+        assert_eq!(parsed.fields.len(), 2);
+
+        let err = Multipart::from_bytes_limited(&bytes, "test-boundary", 1, 6).unwrap_err();
+        assert!(err.to_string().contains("maximum of 1 parts"));
+
+        let err = Multipart::from_bytes_limited(&bytes, "test-boundary", 2, 5).unwrap_err();
+        assert!(err.to_string().contains("maximum size of 5 bytes"));
+        Ok(())
+    }
+
     #[async_std::test]
     async fn test_file_and_text_equivalence() -> Result<()> {
         // The content to write to the file.
@@ -342,4 +4361,29 @@ mod tests {
         let buf = multipart.into_bytes().await.unwrap();
         assert_eq!(expected_size, buf.len());
     }
+
+    #[async_std::test]
+    async fn test_size_hint_file_bytes_base64() -> Result<()> {
+        let mut m = create_multipart();
+        let content = (0..100u8).collect::<Vec<_>>();
+        m.add_file_bytes(
+            "file",
+            "a.bin",
+            &content,
+            "application/octet-stream",
+            Some(Encoding::Base64),
+        )?;
+        let expected_size = m.size_hint().unwrap();
+
+        let buf = m.into_bytes().await?;
+        assert_eq!(expected_size, buf.len());
+        // 100 bytes encode to 136 characters, split into a line of 76 and one of 60
+        let encoded = String::from_utf8(crate::encode_all(&content, Encoding::Base64)).unwrap();
+        assert_eq!(encoded.len(), 138);
+        assert_eq!(encoded.find("\r\n"), Some(76));
+        assert!(String::from_utf8(buf)
+            .unwrap()
+            .contains(&format!("\r\n\r\n{}\r\n--", encoded)));
+        Ok(())
+    }
 }
@@ -0,0 +1,368 @@
+use http_types::{format_err, Result};
+use mime_guess::Mime;
+
+use crate::{part::Part, Encoding};
+
+/// Extracts the subtype and the boundary parameter from a multipart `Content-Type` header value.
+pub(crate) fn parse_content_type(value: &str) -> Result<(String, String)> {
+    let mime: Mime = value.parse()?;
+    if mime.type_() != mime_guess::mime::MULTIPART {
+        return Err(format_err!(
+            "Expected a multipart content type, found `{}`",
+            mime.essence_str()
+        ));
+    }
+    let boundary = mime
+        .get_param("boundary")
+        .map(|b| b.as_str().to_owned())
+        .ok_or_else(|| format_err!("Multipart content type is missing the boundary parameter"))?;
+    Ok((mime.subtype().as_str().to_owned(), boundary))
+}
+
+/// Parses a complete multipart body into its parts.
+///
+/// Anything before the first boundary (the preamble) and after the closing boundary
+/// (the epilogue) is ignored.
+pub(crate) fn parse(body: &[u8], boundary: &str) -> Result<Vec<Part<'static>>> {
+    parse_limited(body, boundary, usize::MAX, usize::MAX)
+}
+
+/// Parses a complete multipart body into its parts, enforcing limits on untrusted input.
+///
+/// Errors as soon as there are more than `max_parts` parts, or a part body is larger than
+/// `max_part_size` bytes, before the offending part is copied.
+pub(crate) fn parse_limited(
+    body: &[u8],
+    boundary: &str,
+    max_parts: usize,
+    max_part_size: usize,
+) -> Result<Vec<Part<'static>>> {
+    parse_parts(body, boundary, max_parts, max_part_size, false)
+}
+
+/// Parses a multipart body nested in a part, such as a `multipart/related` or `multipart/mixed` body.
+///
+/// Unlike [`parse`], parts without a name or filename are accepted, as nested parts usually only
+/// carry headers like `Content-Type` and `Content-ID`.
+pub(crate) fn parse_nested(body: &[u8], boundary: &str) -> Result<Vec<Part<'static>>> {
+    parse_parts(body, boundary, usize::MAX, usize::MAX, true)
+}
+
+/// Parses the parts of a multipart body, rejecting parts without a name or filename unless `allow_nameless` is set.
+fn parse_parts(
+    body: &[u8],
+    boundary: &str,
+    max_parts: usize,
+    max_part_size: usize,
+    allow_nameless: bool,
+) -> Result<Vec<Part<'static>>> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let separator = format!("\r\n--{}", boundary).into_bytes();
+
+    let mut pos = if body.starts_with(&delimiter) {
+        delimiter.len()
+    } else {
+        find(body, &separator).ok_or_else(|| format_err!("Multipart body has no boundary"))?
+            + separator.len()
+    };
+
+    let mut parts = Vec::new();
+    loop {
+        let rest = &body[pos..];
+        if rest.starts_with(b"--") {
+            // Closing boundary
+            return Ok(parts);
+        }
+        let rest = rest
+            .strip_prefix(b"\r\n")
+            .ok_or_else(|| format_err!("Malformed boundary line in multipart body"))?;
+        let end = find(rest, &separator)
+            .ok_or_else(|| format_err!("Multipart body is missing the closing boundary"))?;
+        if parts.len() == max_parts {
+            return Err(format_err!(
+                "Multipart body has more than the maximum of {} parts",
+                max_parts
+            ));
+        }
+        parts.push(parse_part(&rest[..end], max_part_size, allow_nameless)?);
+        pos = body.len() - rest.len() + end + separator.len();
+    }
+}
+
+/// Parses a single part (headers and body, without boundaries).
+fn parse_part(content: &[u8], max_size: usize, allow_nameless: bool) -> Result<Part<'static>> {
+    let (head, body) = if let Some(body) = content.strip_prefix(b"\r\n") {
+        (&[][..], body)
+    } else {
+        let split = find(content, b"\r\n\r\n")
+            .ok_or_else(|| format_err!("Multipart part has no header terminator"))?;
+        (&content[..split], &content[split + 4..])
+    };
+    if body.len() > max_size {
+        return Err(format_err!(
+            "Multipart part of {} bytes exceeds the maximum size of {} bytes",
+            body.len(),
+            max_size
+        ));
+    }
+    let head = std::str::from_utf8(head)?;
+
+    let mut name = None;
+    let mut filename = None;
+    let mut content_type = None;
+    let mut encoding = None;
+    for line in head.split("\r\n").filter(|l| !l.is_empty()) {
+        let (key, value) = line
+            .split_once(':')
+            .ok_or_else(|| format_err!("Malformed part header `{}`", line))?;
+        let value = value.trim();
+        if key.eq_ignore_ascii_case("Content-Disposition") {
+            let (n, f) = parse_disposition(value);
+            name = n;
+            filename = f;
+        } else if key.eq_ignore_ascii_case("Content-Type") {
+            content_type = Some(value.parse::<Mime>()?);
+        } else if key.eq_ignore_ascii_case("Content-Transfer-Encoding") {
+            // `binary` data is sent as is, like a part without the header
+            if !value.eq_ignore_ascii_case("binary") {
+                encoding = Some(value.parse::<Encoding>()?);
+            }
+        }
+    }
+
+    // Hand back the raw bytes, keeping the encoding so the part serializes the same way again
+    let mut body = body.to_vec();
+    if let Some(encoding) = encoding {
+        encoding.decode(&mut body)?;
+    }
+
+    let part = match (name, filename) {
+        (name, Some(filename)) => {
+            let content_type =
+                content_type.unwrap_or_else(|| "application/octet-stream".parse().unwrap());
+            match name {
+                Some(name) => Part::file_raw(name, filename, content_type, encoding, body),
+                None => Part::attachment(filename, content_type, encoding, body),
+            }
+        }
+        (Some(name), None) => {
+            let mut part = Part::text(name, body, encoding);
+            if let Some(content_type) = content_type {
+                part.content_type = content_type;
+            }
+            part
+        }
+        (None, None) if allow_nameless => {
+            // RFC 2046: parts without a `Content-Type` are plain text
+            let content_type = content_type.unwrap_or(mime_guess::mime::TEXT_PLAIN);
+            Part::nameless(content_type, encoding, body)
+        }
+        (None, None) => return Err(format_err!("Multipart part is missing a field name")),
+    };
+    Ok(part)
+}
+
+/// Parses the `name` and `filename` parameters out of a `Content-Disposition` value.
+fn parse_disposition(value: &str) -> (Option<String>, Option<String>) {
+    let mut name = None;
+    let mut filename = None;
+    let mut extended_filename = None;
+    for param in split_params(value).into_iter().skip(1) {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+        match key.trim().to_ascii_lowercase().as_str() {
+            "name" => name = Some(unquote(value.trim())),
+            "filename" => filename = Some(unquote(value.trim())),
+            "filename*" => extended_filename = decode_ext_value(value.trim()),
+            _ => {}
+        }
+    }
+    // RFC 6266: `filename*` takes precedence, `filename` is a fallback for older clients
+    (name, extended_filename.or(filename))
+}
+
+/// Decodes an RFC 5987 extended value, such as `UTF-8''r%C3%A9sum%C3%A9.pdf`.
+///
+/// Returns `None` for charsets other than UTF-8 and ISO-8859-1, or malformed values.
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut pieces = value.splitn(3, '\'');
+    let (charset, _language, encoded) = (pieces.next()?, pieces.next()?, pieces.next()?);
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut input = encoded.bytes();
+    while let Some(byte) = input.next() {
+        if byte == b'%' {
+            let hex = [input.next()?, input.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    if charset.eq_ignore_ascii_case("UTF-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("ISO-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
+
+/// Splits a header value on `;`, ignoring separators inside quoted strings.
+fn split_params(value: &str) -> Vec<&str> {
+    let mut params = Vec::new();
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => {
+                params.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(value[start..].trim());
+    params
+}
+
+/// Removes surrounding quotes and backslash escapes from a parameter value.
+fn unquote(value: &str) -> String {
+    let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) else {
+        return value.to_owned();
+    };
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(next) = chars.next() {
+                out.push(next);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Returns the index of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fields() {
+        let body = b"--b\r\n\
+            Content-Disposition: form-data; name=\"a\"\r\n\r\n\
+            1\r\n\
+            --b\r\n\
+            Content-Disposition: form-data; name=\"f\"; filename=\"x;y.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            hello\r\n\
+            --b--\r\n";
+        let parts = parse(body, "b").unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name(), Some("a"));
+        assert_eq!(parts[0].filename(), None);
+        assert_eq!(parts[1].name(), Some("f"));
+        assert_eq!(parts[1].filename(), Some("x;y.txt"));
+        assert_eq!(parts[1].content_type.essence_str(), "text/plain");
+    }
+
+    #[test]
+    fn test_parse_attachment_without_name() {
+        let body = b"--b\r\n\
+            Content-Disposition: attachment; filename=\"a.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            hello\r\n\
+            --b--\r\n";
+        let parts = parse(body, "b").unwrap();
+        assert_eq!(parts[0].name(), None);
+        assert_eq!(parts[0].filename(), Some("a.txt"));
+    }
+
+    #[test]
+    fn test_parse_decodes_transfer_encoding() {
+        let body = b"--b\r\n\
+            Content-Disposition: form-data; name=\"f\"; filename=\"a.bin\"\r\n\
+            Content-Transfer-Encoding: base64\r\n\r\n\
+            aGVsbG8gd29ybGQ=\r\n\
+            --b\r\n\
+            Content-Disposition: form-data; name=\"q\"\r\n\
+            Content-Transfer-Encoding: quoted-printable\r\n\r\n\
+            caf=C3=A9\r\n\
+            --b--\r\n";
+        let parts = parse(body, "b").unwrap();
+        assert_eq!(parts[0].data.as_bytes(), Some(&b"hello world"[..]));
+        assert_eq!(parts[0].encoding(), Some(Encoding::Base64));
+        assert_eq!(parts[1].data.as_bytes(), Some("café".as_bytes()));
+    }
+
+    #[test]
+    fn test_parse_line_wrapped_base64() {
+        let data = (0..=255).collect::<Vec<u8>>();
+        let encoded = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &data);
+        let wrapped = encoded
+            .as_bytes()
+            .chunks(76)
+            .map(|line| std::str::from_utf8(line).unwrap())
+            .collect::<Vec<_>>()
+            .join("\r\n");
+        let body = format!(
+            "--b\r\n\
+            Content-Disposition: form-data; name=\"f\"; filename=\"a.bin\"\r\n\
+            Content-Transfer-Encoding: base64\r\n\r\n\
+            {}\r\n\
+            --b--\r\n",
+            wrapped
+        );
+        let parts = parse(body.as_bytes(), "b").unwrap();
+        assert_eq!(parts[0].data.as_bytes(), Some(&data[..]));
+    }
+
+    #[test]
+    fn test_parse_binary_transfer_encoding() {
+        let body = b"--b\r\n\
+            Content-Disposition: form-data; name=\"f\"; filename=\"a.bin\"\r\n\
+            Content-Transfer-Encoding: Binary\r\n\r\n\
+            \x00\xff raw\r\n\
+            --b--\r\n";
+        let parts = parse(body, "b").unwrap();
+        assert_eq!(parts[0].data.as_bytes(), Some(&b"\x00\xff raw"[..]));
+        assert_eq!(parts[0].encoding(), None);
+    }
+
+    #[test]
+    fn test_parse_disposition_extended_filename() {
+        let (name, filename) = parse_disposition(
+            "attachment; filename=\"fallback.txt\"; filename*=UTF-8'en'na%C3%AFve%20file.txt",
+        );
+        assert_eq!(name, None);
+        assert_eq!(filename.as_deref(), Some("naïve file.txt"));
+
+        let (_, filename) = parse_disposition("attachment; filename*=iso-8859-1''caf%E9.txt");
+        assert_eq!(filename.as_deref(), Some("café.txt"));
+
+        // Unsupported or malformed extended values fall back to `filename`
+        for value in ["KOI8-R''x.txt", "UTF-8''%E9.txt", "UTF-8''%4"] {
+            let disposition = format!("attachment; filename*={value}; filename=\"plain.txt\"");
+            let (_, filename) = parse_disposition(&disposition);
+            assert_eq!(filename.as_deref(), Some("plain.txt"));
+        }
+    }
+
+    #[test]
+    fn test_parse_missing_closing_boundary() {
+        let body = b"--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1";
+        assert!(parse(body, "b").is_err());
+    }
+}
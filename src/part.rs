@@ -1,56 +1,219 @@
-use std::{borrow::Cow, io::Write, path::Path};
+use std::{borrow::Cow, path::Path};
 
 use async_fs::File as AsyncFile;
-use base64::Engine;
-use futures_lite::{io::BufReader, AsyncBufRead, AsyncReadExt, Stream, StreamExt};
+use futures_lite::{
+    io::{AssertAsync, BufReader},
+    AsyncBufRead, AsyncRead, AsyncReadExt, Stream, StreamExt,
+};
 use http_types::Body;
 use mime_guess::Mime;
 
-use crate::{reader_stream::ReaderStream, Encoding, StreamChunk};
+use crate::{
+    lazy_file::LazyFile,
+    multipart::quote_param,
+    reader_stream::{auto_buf_size, ReaderStream},
+    ContentDisposition, Encoding, StreamChunk,
+};
+
+/// The body of a part.
+#[derive(Debug)]
+pub(crate) enum PartData {
+    /// Data held in memory, which can be inspected without consuming the part.
+    Bytes(Vec<u8>),
+    /// Data backed by a reader, which can only be read once.
+    Body(Body),
+}
+
+impl PartData {
+    /// Returns the length of the unencoded data, if known.
+    pub(crate) fn len(&self) -> Option<usize> {
+        match self {
+            PartData::Bytes(bytes) => Some(bytes.len()),
+            PartData::Body(body) => body.len(),
+        }
+    }
+
+    /// Returns the unencoded data if it is held in memory.
+    pub(crate) fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            PartData::Bytes(bytes) => Some(bytes),
+            PartData::Body(_) => None,
+        }
+    }
+
+    pub(crate) fn into_reader(self) -> Box<dyn AsyncBufRead + Unpin + Send + Sync + 'static> {
+        match self {
+            PartData::Bytes(bytes) => Box::new(futures_lite::io::Cursor::new(bytes)),
+            PartData::Body(body) => body.into_reader(),
+        }
+    }
+}
+
+impl From<&[u8]> for PartData {
+    fn from(bytes: &[u8]) -> Self {
+        PartData::Bytes(bytes.to_vec())
+    }
+}
+
+impl From<Vec<u8>> for PartData {
+    fn from(bytes: Vec<u8>) -> Self {
+        PartData::Bytes(bytes)
+    }
+}
+
+impl From<Body> for PartData {
+    fn from(body: Body) -> Self {
+        PartData::Body(body)
+    }
+}
 
 /// Represents a single field in a multipart form.
 #[derive(Debug)]
 pub(crate) struct Part<'p> {
-    name: Cow<'p, str>,
-    data: Body,
+    name: Option<Cow<'p, str>>,
+    pub(crate) data: PartData,
+    /// The type of the data before transfer encoding.
     pub(crate) content_type: Mime,
     file_data: Option<Cow<'p, str>>,
     encoding: Option<Encoding>,
+    /// Whether the data is an already serialized part (headers and body) to emit verbatim.
+    pre_serialized: bool,
+    /// Overrides the disposition type, which is otherwise `form-data` for named parts
+    /// and `attachment` for nameless ones.
+    disposition_type: Option<Cow<'p, str>>,
+    /// Extra `Content-Disposition` parameters, written after `name` and `filename`.
+    disposition_params: Vec<(Cow<'p, str>, Cow<'p, str>)>,
+    /// Extra headers, such as `Content-ID`, written after the standard ones in insertion order.
+    headers: Vec<(String, String)>,
 }
 
 impl<'p> Part<'p> {
+    /// Returns the field name of the part.
+    /// Parts of `multipart/mixed` forms may not have one.
+    pub(crate) fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     /// Returns the filename of the part.
     /// Only applicable to file parts.
     pub(crate) fn filename(&self) -> Option<&str> {
         self.file_data.as_deref()
     }
 
+    /// Replaces the field name of the part.
+    pub(crate) fn set_name(&mut self, name: impl Into<Cow<'p, str>>) {
+        self.name = Some(name.into());
+    }
+
+    /// Replaces the transfer encoding of the part.
+    pub(crate) fn set_encoding(&mut self, encoding: Option<Encoding>) {
+        self.encoding = encoding;
+    }
+
+    /// Replaces the filename of a file part.
+    pub(crate) fn set_filename(&mut self, filename: impl Into<Cow<'p, str>>) {
+        self.file_data = Some(filename.into());
+    }
+
+    /// Returns a copy of the part if its data is held in memory, reader-backed parts can only be read once.
+    pub(crate) fn try_clone(&self) -> Option<Part<'p>> {
+        let data = self.data.as_bytes()?.to_vec();
+        Some(Part {
+            name: self.name.clone(),
+            data: PartData::Bytes(data),
+            content_type: self.content_type.clone(),
+            file_data: self.file_data.clone(),
+            encoding: self.encoding,
+            pre_serialized: self.pre_serialized,
+            disposition_type: self.disposition_type.clone(),
+            disposition_params: self.disposition_params.clone(),
+            headers: self.headers.clone(),
+        })
+    }
+
+    /// Adds a header written after the standard ones, keeping insertion order.
+    pub(crate) fn add_header(&mut self, name: String, value: String) {
+        self.headers.push((name, value));
+    }
+
+    /// Compares the headers and, for in-memory parts, the data of two parts.
+    ///
+    /// Reader-backed parts can't be inspected without consuming them, so they never compare equal.
+    pub(crate) fn structural_eq(&self, other: &Part<'_>) -> bool {
+        let same_data = match (self.data.as_bytes(), other.data.as_bytes()) {
+            (Some(data), Some(other_data)) => data == other_data,
+            _ => false,
+        };
+        same_data
+            && self.name == other.name
+            && self.file_data == other.file_data
+            && self.content_type == other.content_type
+            && self.encoding == other.encoding
+            && self.pre_serialized == other.pre_serialized
+            && self.disposition_type() == other.disposition_type()
+            && self.disposition_params == other.disposition_params
+            && self.headers == other.headers
+    }
+
+    /// Returns the disposition type written in the `Content-Disposition` header.
+    pub(crate) fn disposition_type(&self) -> &str {
+        match (&self.disposition_type, self.name()) {
+            (Some(disposition_type), _) => disposition_type,
+            (None, Some(_)) => "form-data",
+            (None, None) => "attachment",
+        }
+    }
+
     /// Returns the encoding of the part.
     /// Only applicable to file parts.
     pub(crate) fn encoding(&self) -> Option<Encoding> {
         self.encoding
     }
 
+    /// Returns the content type of the data once its transfer encoding is undone.
+    ///
+    /// The transfer encoding never changes the declared type: a base64-encoded PNG is still
+    /// sent as `Content-Type: image/png` with `Content-Transfer-Encoding: base64`.
+    pub(crate) fn decoded_content_type(&self) -> &Mime {
+        &self.content_type
+    }
+
     /// Returns the data of the part as a stream.
     ///
     /// This is recommended for large files, as it will stream the file instead of loading it into memory.
     /// This also streams text values as bytes.
     ///
+    /// The header block is always yielded as its own chunk, separate from the first body chunk,
+    /// so consumers can flush after the headers before the body starts.
+    ///
+    /// Without an explicit `buf_size`, the chunk size is picked from the data length when it is known.
+    ///
     /// Remember to place the boundary between parts when using this stream.
     pub(crate) fn into_stream(self, buf_size: Option<usize>) -> impl Stream<Item = StreamChunk> {
         let header = self.header_bytes();
-        let header_stream = futures_lite::stream::once(Ok(header));
-        let buf_size = buf_size.or(self.data.len());
+        // Pre-serialized parts carry their headers in the data
+        let header_stream = futures_lite::stream::iter((!header.is_empty()).then_some(Ok(header)));
+        let buf_size = self.buf_size(buf_size);
         let encoding = self.encoding();
         let data = ReaderStream::new(self.data.into_reader(), buf_size, encoding);
         header_stream.chain(data)
     }
 
+    /// Returns the chunk size the part is read with: `buf_size` if given, or one picked from the data length when it is known.
+    pub(crate) fn buf_size(&self, buf_size: Option<usize>) -> Option<usize> {
+        buf_size.or_else(|| self.data.len().map(auto_buf_size))
+    }
+
+    /// Returns whether the data is read from a reader rather than held in memory.
+    pub(crate) fn is_reader_backed(&self) -> bool {
+        self.data.as_bytes().is_none()
+    }
+
     pub(crate) fn into_reader(self, buf_size: Option<usize>) -> impl AsyncBufRead {
         let header = self.header_bytes();
         let header_reader = futures_lite::io::Cursor::new(header);
         let encoding = self.encoding();
-        let buf_size = buf_size.or(self.data.len());
+        let buf_size = self.buf_size(buf_size);
         let data_reader = self.data.into_reader();
         let data = ReaderStream::new(data_reader, buf_size, encoding);
         header_reader.chain(data)
@@ -63,11 +226,15 @@ impl<'p> Part<'p> {
         encoding: Option<Encoding>,
     ) -> Self {
         Part {
-            name: name.into(),
-            data: Body::from(value.as_ref()),
+            name: Some(name.into()),
+            data: PartData::Bytes(value.as_ref().to_vec()),
             content_type: "text/plain".parse().unwrap(),
             encoding,
             file_data: None,
+            pre_serialized: false,
+            disposition_type: None,
+            disposition_params: Vec::new(),
+            headers: Vec::new(),
         }
     }
 
@@ -77,14 +244,18 @@ impl<'p> Part<'p> {
         filename: impl Into<Cow<'p, str>>,
         content_type: Mime,
         encoding: Option<Encoding>,
-        data: impl Into<Body>,
+        data: impl Into<PartData>,
     ) -> Self {
         Part {
-            name: name.into(),
+            name: Some(name.into()),
             data: data.into(),
             content_type,
             encoding,
             file_data: Some(filename.into()),
+            pre_serialized: false,
+            disposition_type: None,
+            disposition_params: Vec::new(),
+            headers: Vec::new(),
         }
     }
 
@@ -98,11 +269,92 @@ impl<'p> Part<'p> {
         data_len: Option<usize>, // Optional length for the reader, if known
     ) -> Self {
         Part {
-            name: name.into(),
+            name: Some(name.into()),
+            content_type,
+            data: PartData::Body(Body::from_reader(data, data_len)),
+            encoding,
+            file_data: Some(filename.into()),
+            pre_serialized: false,
+            disposition_type: None,
+            disposition_params: Vec::new(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Creates a new nameless file part, as used for attachments in `multipart/mixed` forms.
+    pub(crate) fn attachment(
+        filename: impl Into<Cow<'p, str>>,
+        content_type: Mime,
+        encoding: Option<Encoding>,
+        data: impl Into<PartData>,
+    ) -> Self {
+        Part {
+            name: None,
+            data: data.into(),
             content_type,
-            data: Body::from_reader(data, data_len),
             encoding,
             file_data: Some(filename.into()),
+            pre_serialized: false,
+            disposition_type: None,
+            disposition_params: Vec::new(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Creates a part without a name or filename, such as a child of a nested `multipart/related` body.
+    pub(crate) fn nameless(
+        content_type: Mime,
+        encoding: Option<Encoding>,
+        data: impl Into<PartData>,
+    ) -> Self {
+        Part {
+            name: None,
+            data: data.into(),
+            content_type,
+            encoding,
+            file_data: None,
+            pre_serialized: false,
+            disposition_type: None,
+            disposition_params: Vec::new(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Creates a part from a reader with an exact `Content-Disposition` header.
+    pub(crate) fn with_disposition(
+        disposition: ContentDisposition<'p>,
+        content_type: Mime,
+        encoding: Option<Encoding>,
+        data: impl AsyncBufRead + Unpin + Send + Sync + 'static,
+        data_len: Option<usize>,
+    ) -> Self {
+        Part {
+            name: disposition.name,
+            data: PartData::Body(Body::from_reader(data, data_len)),
+            content_type,
+            encoding,
+            file_data: disposition.filename,
+            pre_serialized: false,
+            disposition_type: Some(disposition.disposition_type),
+            disposition_params: disposition.params,
+            headers: Vec::new(),
+        }
+    }
+
+    /// Creates a part from an already serialized block (headers, blank line and body, without boundaries).
+    ///
+    /// The bytes are emitted verbatim, so no header is generated and no encoding is applied.
+    pub(crate) fn pre_serialized(bytes: Vec<u8>) -> Self {
+        Part {
+            name: None,
+            data: PartData::Bytes(bytes),
+            content_type: mime_guess::mime::APPLICATION_OCTET_STREAM,
+            encoding: None,
+            file_data: None,
+            pre_serialized: true,
+            disposition_type: None,
+            disposition_params: Vec::new(),
+            headers: Vec::new(),
         }
     }
 
@@ -119,73 +371,219 @@ impl<'p> Part<'p> {
     ) -> Result<Self, futures_lite::io::Error> {
         let path = path.as_ref();
         let filename = filename(path);
-        let content_type =
-            content_type(path).unwrap_or_else(|| "application/octet-stream".parse().unwrap());
         let file = AsyncFile::open(path).await?;
-        let mut data_len = file.metadata().await?.len() as usize;
-        if let Some(Encoding::Base64) = encoding {
-            // Base64 encoding increases the size of the data
-            data_len = (data_len * 4 + 2) / 3; // Rough estimate for base64 size
-        }
-        let buf_reader = BufReader::new(file);
-        Ok(Part::file_raw_async(
+        // The raw length, `size_hint` accounts for the encoding
+        let data_len = file.metadata().await?.len() as usize;
+        Ok(Part::open_file(
             name,
             filename,
-            content_type,
+            file,
+            Some(data_len),
+            encoding,
+        ))
+    }
+
+    /// Creates a new file part from a path, without opening the file until the part is read.
+    ///
+    /// The length is taken from the file metadata now, so the form length stays known.
+    pub(crate) async fn file_lazy(
+        name: impl Into<Cow<'p, str>>,
+        path: impl AsRef<Path>,
+        encoding: Option<Encoding>,
+    ) -> Result<Self, futures_lite::io::Error> {
+        let path = path.as_ref();
+        let data_len = async_fs::metadata(path).await?.len() as usize;
+        Ok(Part::open_file(
+            name,
+            filename(path),
+            LazyFile::new(path),
+            Some(data_len),
             encoding,
-            buf_reader,
+        ))
+    }
+
+    /// Creates a new file part from a path, using blocking `std::fs` calls.
+    ///
+    /// The length is read from the file metadata, the contents are read as the part is streamed.
+    pub(crate) fn from_path_sync(
+        name: impl Into<Cow<'p, str>>,
+        path: impl AsRef<Path>,
+        encoding: Option<Encoding>,
+    ) -> Result<Self, futures_lite::io::Error> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)?;
+        let data_len = file.metadata()?.len() as usize;
+        Ok(Part::open_file(
+            name,
+            filename(path),
+            AssertAsync::new(file),
             Some(data_len),
+            encoding,
         ))
     }
 
-    pub(crate) fn size_hint(&self) -> Option<usize> {
-        let mut data_len = self.data.len()?;
-        if let Some(Encoding::Base64) = self.encoding {
-            data_len *= 4 / 3;
+    /// Creates a new file part from an already opened file, or any other reader.
+    ///
+    /// The content type is guessed from the filename.
+    pub(crate) fn open_file(
+        name: impl Into<Cow<'p, str>>,
+        filename: impl Into<Cow<'p, str>>,
+        file: impl AsyncRead + Unpin + Send + Sync + 'static,
+        data_len: Option<usize>,
+        encoding: Option<Encoding>,
+    ) -> Self {
+        let filename = filename.into();
+        let content_type = content_type(Path::new(filename.as_ref()))
+            .unwrap_or_else(|| "application/octet-stream".parse().unwrap());
+        Part::file_raw_async(
+            name,
+            filename,
+            content_type,
+            encoding,
+            BufReader::new(file),
+            data_len,
+        )
+    }
+
+    /// Returns the exact serialized length of the part, if it can be known without reading the data.
+    ///
+    /// Returns `None` when the data length is unknown, or for quoted-printable parts,
+    /// whose encoded length depends on the content.
+    pub(crate) fn content_length(&self) -> Option<u64> {
+        (self.header_len() as u64).checked_add(self.body_content_length()?)
+    }
+
+    /// Returns the exact encoded length of the body, if it can be known without reading the data.
+    pub(crate) fn body_size_hint(&self) -> Option<usize> {
+        self.body_content_length()?.try_into().ok()
+    }
+
+    fn body_content_length(&self) -> Option<u64> {
+        let data_len = self.data.len()? as u64;
+        match self.encoding {
+            Some(encoding) => encoding.encoded_len_u64(data_len),
+            None => Some(data_len),
         }
-        let header_len = self.header_len();
-        Some((data_len + header_len))
     }
 
-    fn header_len(&self) -> usize {
-        // Calculate the length of the headers to be written
-        // Initial part: "Content-Disposition: form-data; name=\"[name]\""
-        let mut len = 39 + self.name.len(); // 41 = "Content-Disposition: form-data; name=\"\"".len()
-        if let Some(filename) = self.filename() {
-            // Add "; filename=\"[filename]\"" if this is a file part
-            len += 15 + filename.len(); // 15 = "; filename=\"\"".len()
+    /// Returns the exact streamed length of the part, encoding in-memory data to measure it.
+    ///
+    /// Errors if the part is backed by a reader whose encoded length can't be known without consuming it.
+    pub(crate) async fn stream_len(&self) -> Result<usize, futures_lite::io::Error> {
+        let header_len = self.header_bytes().len();
+        let data_len =
+            match (&self.data, self.encoding) {
+                (PartData::Bytes(bytes), encoding) => {
+                    let reader = futures_lite::io::Cursor::new(bytes.as_slice());
+                    let mut stream = ReaderStream::new(reader, Some(bytes.len()), encoding);
+                    let mut len = 0;
+                    while let Some(chunk) = stream.next().await {
+                        len += chunk?.len();
+                    }
+                    len
+                }
+                (PartData::Body(body), None) => body.len().ok_or_else(|| {
+                    futures_lite::io::Error::other("Length of reader-backed part is unknown")
+                })?,
+                (PartData::Body(_), Some(_)) => return Err(futures_lite::io::Error::other(
+                    "Encoded length of reader-backed part can't be computed without consuming it",
+                )),
+            };
+        Ok(header_len + data_len)
+    }
+
+    pub(crate) fn header_len(&self) -> usize {
+        if self.pre_serialized {
+            return 0;
         }
+        // Calculate the length of the headers to be written
+        // "Content-Disposition: [disposition]" line
+        let mut len = 21 + self.content_disposition().len(); // 21 = "Content-Disposition: ".len()
         len += 2; // CRLF after Content-Disposition line
-                  // "Content-Type: [content_type]" line
-        len += 14 + self.content_type.essence_str().len(); // 14 = "Content-Type: ".len()
+
+        // "Content-Type: [content_type]" line
+        // The full type including any parameters, as written by `write_header`
+        len += 14 + self.content_type.as_ref().len(); // 14 = "Content-Type: ".len()
         len += 2; // CRLF after Content-Type
         if let Some(encoding) = self.encoding() {
             // "Content-Transfer-Encoding: [encoding]" line
             len += 27 + encoding.to_str().len(); // 27 = "Content-Transfer-Encoding: ".len()
             len += 2; // CRLF after Content-Transfer-Encoding
         }
+        for (name, value) in &self.headers {
+            len += name.len() + 2 + value.len() + 2; // "[name]: [value]" and CRLF
+        }
         len + 2 // Final CRLF that separates headers from body
     }
 
     fn write_header<W: std::io::Write>(&self, mut buf: W) -> Result<(), std::io::Error> {
-        buf.write_all(
-            format!("Content-Disposition: form-data; name=\"{}\"", self.name).as_bytes(),
-        )?;
-        if let Some(filename) = self.filename() {
-            buf.write_all(format!("; filename=\"{}\"", filename).as_bytes())?;
+        if self.pre_serialized {
+            return Ok(());
         }
-        buf.write_all(b"\r\n")?;
+        write!(
+            buf,
+            "Content-Disposition: {}\r\n",
+            self.content_disposition()
+        )?;
         buf.write_all(format!("Content-Type: {}\r\n", self.content_type).as_bytes())?;
         if let Some(encoding) = self.encoding() {
             buf.write_all(
                 format!("Content-Transfer-Encoding: {}\r\n", encoding.to_str()).as_bytes(),
             )?;
         }
+        for (name, value) in &self.headers {
+            write!(buf, "{}: {}\r\n", name, value)?;
+        }
         buf.write_all(b"\r\n")?; // Blank line to separate headers from body
         Ok(())
     }
 
-    fn header_bytes(&self) -> Vec<u8> {
+    /// Returns the value of the `Content-Disposition` header emitted for the part,
+    /// such as `form-data; name="file"; filename="a.txt"`.
+    pub(crate) fn content_disposition(&self) -> String {
+        let mut disposition = self.disposition_type().to_owned();
+        if let Some(name) = self.name() {
+            disposition += &format!("; name=\"{}\"", escape_quoted(name));
+        }
+        if let Some(filename) = self.filename_params() {
+            disposition += &filename;
+        }
+        for (name, value) in &self.disposition_params {
+            disposition += &format!("; {}={}", name, quote_param(value));
+        }
+        disposition
+    }
+
+    /// Returns the `filename` parameter of the `Content-Disposition` header, including its leading `; `.
+    ///
+    /// Outside of `form-data`, a non-ASCII filename is sent RFC 6266 style: an ASCII fallback in
+    /// `filename`, and the exact name percent-encoded in `filename*`. RFC 7578 forbids `filename*`
+    /// in `form-data`, where the UTF-8 name is sent as is.
+    fn filename_params(&self) -> Option<String> {
+        let filename = self.filename()?;
+        if filename.is_ascii() || self.disposition_type().eq_ignore_ascii_case("form-data") {
+            return Some(format!("; filename=\"{}\"", escape_quoted(filename)));
+        }
+        let fallback = filename
+            .chars()
+            .map(|c| if c.is_ascii() { c } else { '_' })
+            .collect::<String>();
+        let mut encoded = String::with_capacity(filename.len() * 3);
+        for byte in filename.bytes() {
+            if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+                encoded.push(byte as char);
+            } else {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        Some(format!(
+            "; filename=\"{}\"; filename*=UTF-8''{}",
+            escape_quoted(&fallback),
+            encoded
+        ))
+    }
+
+    pub(crate) fn header_bytes(&self) -> Vec<u8> {
         let mut header = Vec::with_capacity(self.header_len());
         self.write_header(&mut header)
             .expect("Failed to write header");
@@ -193,27 +591,31 @@ impl<'p> Part<'p> {
     }
 
     /// Extends the data of the part into a buffer.
-    pub(crate) async fn extend(
-        self,
-        mut data: &mut Vec<u8>,
-    ) -> Result<(), futures_lite::io::Error> {
-        self.write_header(&mut data)?;
-        let encoding = self.encoding;
+    /// The stream already contains the header block and the encoded body.
+    pub(crate) async fn extend(self, data: &mut Vec<u8>) -> Result<(), futures_lite::io::Error> {
         let mut stream = self.into_stream(None);
         while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
-            if let Some(Encoding::Base64) = encoding {
-                // Encode if necessary (base64 encoding)
-                let encoded_chunk = base64::engine::general_purpose::STANDARD.encode(&chunk);
-                data.extend_from_slice(encoded_chunk.as_bytes());
-                continue;
-            }
-            data.extend_from_slice(&chunk);
+            data.extend_from_slice(&chunk?);
         }
         Ok(())
     }
 }
 
+/// Percent-encodes `"`, `\`, CR and LF in a quoted `name` or `filename` value, as browsers do
+/// for `form-data` (RFC 7578), so a value can't end the quoted string or inject header lines.
+fn escape_quoted(value: &str) -> Cow<'_, str> {
+    if !value.contains(['"', '\\', '\r', '\n']) {
+        return Cow::Borrowed(value);
+    }
+    Cow::Owned(
+        value
+            .replace('"', "%22")
+            .replace('\\', "%5C")
+            .replace('\r', "%0D")
+            .replace('\n', "%0A"),
+    )
+}
+
 /// Returns the filename of a path.
 /// If the path has no filename, it returns "file".
 fn filename(path: &Path) -> String {
@@ -257,21 +659,137 @@ mod tests {
         assert_eq!(stream_output, reader_output);
     }
 
+    #[async_std::test]
+    async fn test_stream_buffer_scales_with_length() {
+        let body_chunks = |part: Part<'static>| async move {
+            let chunks = part.into_stream(None).collect::<Vec<_>>().await;
+            chunks[1..]
+                .iter()
+                .map(|chunk| chunk.as_ref().unwrap().len())
+                .collect::<Vec<_>>()
+        };
+
+        let tiny = Part::text("tiny", "small", None);
+        assert_eq!(body_chunks(tiny).await, [5]);
+
+        let large = Part::text("large", vec![b'x'; 1_600_000], None);
+        let chunks = body_chunks(large).await;
+        assert_eq!(chunks.len(), 16);
+        assert!(chunks.iter().all(|len| *len == 100_000));
+    }
+
+    #[async_std::test]
+    async fn test_base64_stream_default_buffer_with_short_reads() {
+        // 1000 bytes isn't a multiple of 3, and the chained reader stops short after 500 bytes
+        let data = (0..=255).cycle().take(1000).collect::<Vec<u8>>();
+        let (first, second) = data.split_at(500);
+        let reader = futures_lite::io::Cursor::new(first.to_vec())
+            .chain(futures_lite::io::Cursor::new(second.to_vec()));
+        let part = Part::file_raw_async(
+            "file",
+            "data.bin",
+            "application/octet-stream".parse().unwrap(),
+            Some(Encoding::Base64),
+            reader,
+            Some(data.len()),
+        );
+        let header_len = part.header_len();
+        let mut output = Vec::new();
+        let mut stream = Box::pin(part.into_stream(None));
+        while let Some(chunk) = stream.next().await {
+            output.extend(chunk.unwrap());
+        }
+
+        let mut decoded = output.split_off(header_len);
+        Encoding::Base64.decode(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_decoded_content_type() {
+        let png = "image/png".parse().unwrap();
+        let part = Part::file_raw(
+            "image",
+            "a.png",
+            png,
+            Some(Encoding::Base64),
+            &b"\x89PNG"[..],
+        );
+        assert_eq!(part.decoded_content_type().essence_str(), "image/png");
+
+        let header = String::from_utf8(part.header_bytes()).unwrap();
+        assert!(header.contains("Content-Type: image/png\r\n"));
+        assert!(header.contains("Content-Transfer-Encoding: base64\r\n"));
+    }
+
+    #[async_std::test]
+    async fn test_stream_header_is_first_chunk() {
+        let part = Part::text("field", "Hello world!", None);
+        let header = part.header_bytes();
+
+        let mut stream = part.into_stream(Some(4));
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(first, header);
+
+        let second = stream.next().await.unwrap().unwrap();
+        assert_eq!(second, b"Hell");
+    }
+
     #[async_std::test]
     async fn test_part_size_hint_no_encoding() {
         let part = Part::text("field", "Hello world!", None);
-        let expected_size = part.size_hint().unwrap();
+        let expected_size = part.content_length().unwrap() as usize;
         let mut buf = Vec::new();
         part.extend(&mut buf).await.expect("extend failed");
         assert_eq!(expected_size, buf.len());
     }
 
     #[async_std::test]
-    async fn test_part_size_hint_base64_encoding() {
-        let part = Part::text("field_base64", "Hello world!", Some(Encoding::Base64));
-        let expected_size = part.size_hint().unwrap();
+    async fn test_attachment_part_has_no_name() {
+        let part = Part::attachment(
+            "notes.txt",
+            mime_guess::mime::TEXT_PLAIN,
+            None,
+            &b"notes"[..],
+        );
+        let expected_size = part.content_length().unwrap() as usize;
         let mut buf = Vec::new();
         part.extend(&mut buf).await.expect("extend failed");
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.starts_with("Content-Disposition: attachment; filename=\"notes.txt\"\r\n"));
+        assert!(!output.contains("; name="));
+        assert_eq!(expected_size, output.len());
+    }
+
+    #[async_std::test]
+    async fn test_part_size_hint_parameterized_mime() {
+        // `mime_guess` only yields bare types today, so use an explicit parameterized one.
+        let mime: Mime = "text/plain; charset=utf-8".parse().unwrap();
+        let part = Part::file_raw("field", "notes.txt", mime, None, &b"Hello world!"[..]);
+        let expected_size = part.content_length().unwrap() as usize;
+        let mut buf = Vec::new();
+        part.extend(&mut buf).await.expect("extend failed");
+        assert!(
+            String::from_utf8_lossy(&buf).contains("Content-Type: text/plain; charset=utf-8\r\n")
+        );
         assert_eq!(expected_size, buf.len());
     }
+
+    #[async_std::test]
+    async fn test_part_size_hint_base64_encoding() {
+        // Lengths that aren't a multiple of 3 end in a partial base64 group
+        for value in ["Hello world!", "Hello world", "Hello worl", "H", ""] {
+            for encoding in [
+                Encoding::Base64,
+                Encoding::Base64UrlSafe,
+                Encoding::Base64UrlSafePadded,
+            ] {
+                let part = Part::text("field_base64", value, Some(encoding));
+                let expected_size = part.content_length().unwrap() as usize;
+                let mut buf = Vec::new();
+                part.extend(&mut buf).await.expect("extend failed");
+                assert_eq!(expected_size, buf.len(), "{:?} as {:?}", value, encoding);
+            }
+        }
+    }
 }
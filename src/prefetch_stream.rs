@@ -0,0 +1,140 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_lite::Stream;
+
+use crate::StreamChunk;
+
+type Segment = Pin<Box<dyn Stream<Item = StreamChunk>>>;
+
+struct Source {
+    stream: Segment,
+    /// Chunks read ahead of time, yielded before polling the stream again
+    buffered: VecDeque<StreamChunk>,
+    done: bool,
+}
+
+/// Yields the chunks of several streams in order, reading ahead from the upcoming
+/// streams while the current one is being consumed.
+///
+/// Reading ahead stops once `max_buffered` bytes are buffered across all upcoming streams,
+/// so the buffer can exceed it by at most one chunk.
+pub(crate) struct PrefetchStream {
+    sources: VecDeque<Source>,
+    max_buffered: usize,
+    buffered_len: usize,
+}
+
+impl PrefetchStream {
+    pub(crate) fn new(segments: impl IntoIterator<Item = Segment>, max_buffered: usize) -> Self {
+        let sources = segments
+            .into_iter()
+            .map(|stream| Source {
+                stream,
+                buffered: VecDeque::new(),
+                done: false,
+            })
+            .collect();
+        Self {
+            sources,
+            max_buffered,
+            buffered_len: 0,
+        }
+    }
+
+    /// Polls the upcoming streams until they are pending, finished, or the budget is used up.
+    fn prefetch(&mut self, cx: &mut Context<'_>) {
+        for source in self.sources.iter_mut().skip(1) {
+            while !source.done && self.buffered_len < self.max_buffered {
+                match source.stream.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(chunk)) => {
+                        // Nothing after an error is read, it is yielded in its place
+                        source.done = chunk.is_err();
+                        self.buffered_len += chunk.as_ref().map_or(0, Vec::len);
+                        source.buffered.push_back(chunk);
+                    }
+                    Poll::Ready(None) => source.done = true,
+                    Poll::Pending => break,
+                }
+            }
+            if self.buffered_len >= self.max_buffered {
+                break;
+            }
+        }
+    }
+}
+
+impl Stream for PrefetchStream {
+    type Item = StreamChunk;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.prefetch(cx);
+        while let Some(current) = this.sources.front_mut() {
+            if let Some(chunk) = current.buffered.pop_front() {
+                this.buffered_len -= chunk.as_ref().map_or(0, Vec::len);
+                return Poll::Ready(Some(chunk));
+            }
+            if current.done {
+                this.sources.pop_front();
+                // The next stream is now current, so its buffer no longer limits the others
+                this.prefetch(cx);
+                continue;
+            }
+            match current.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(chunk)) => {
+                    current.done = chunk.is_err();
+                    return Poll::Ready(Some(chunk));
+                }
+                Poll::Ready(None) => current.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::{stream, StreamExt};
+
+    fn segment(chunks: &[&[u8]]) -> Segment {
+        let chunks = chunks.iter().map(|c| Ok(c.to_vec())).collect::<Vec<_>>();
+        Box::pin(stream::iter(chunks))
+    }
+
+    #[async_std::test]
+    async fn test_prefetch_respects_budget() {
+        let mut stream =
+            PrefetchStream::new([segment(&[b"a", b"b"]), segment(&[b"cc", b"dd", b"ee"])], 3);
+        assert_eq!(stream.next().await.unwrap().unwrap(), b"a");
+        // The second stream is read ahead only up to the budget
+        assert_eq!(stream.sources[1].buffered.len(), 2);
+        assert_eq!(stream.buffered_len, 4);
+
+        let mut output = b"a".to_vec();
+        while let Some(chunk) = stream.next().await {
+            output.extend(chunk.unwrap());
+        }
+        assert_eq!(output, b"abccddee");
+        assert_eq!(stream.buffered_len, 0);
+    }
+
+    #[async_std::test]
+    async fn test_prefetch_stops_after_error() {
+        let failing: Segment = Box::pin(stream::iter(vec![
+            Ok(b"x".to_vec()),
+            Err(std::io::Error::other("boom")),
+            Ok(b"unreachable".to_vec()),
+        ]));
+        let mut stream = PrefetchStream::new([segment(&[b"a"]), failing], 1024);
+        assert_eq!(stream.next().await.unwrap().unwrap(), b"a");
+        assert_eq!(stream.next().await.unwrap().unwrap(), b"x");
+        assert!(stream.next().await.unwrap().is_err());
+        assert!(stream.next().await.is_none());
+    }
+}
@@ -1,41 +1,203 @@
 use std::{
+    io,
     pin::Pin,
     task::{Context, Poll},
 };
 
 use futures_lite::{AsyncBufRead, AsyncRead, Stream};
 
-use crate::{Encoding, StreamChunk};
+use crate::{encoding::encode_qp_chunk, Encoding, StreamChunk};
 
-const CHUNK_SIZE: usize = 256;
+pub(crate) const CHUNK_SIZE: usize = 256;
+const MIN_AUTO_CHUNK_SIZE: usize = 4 * 1024;
+const MAX_AUTO_CHUNK_SIZE: usize = 1024 * 1024;
 
 pub(crate) struct ReaderStream<R> {
     reader: R,
     buf_size: usize,
-    buf_buffer: Option<Vec<u8>>,
+    /// The encoded chunk handed out by `poll_fill_buf`
+    buf_buffer: Vec<u8>,
+    /// How much of `buf_buffer` has been consumed
+    buf_pos: usize,
     encoding: Option<Encoding>,
+    /// Whether the encoding's prefix has been emitted
+    started: bool,
+    /// Whether the encoding's suffix has been emitted
+    finished: bool,
+    /// Data read towards the next chunk, for encodings that need whole chunks
+    pending: Vec<u8>,
+    /// The unfinished last line of the data read so far, for quoted-printable
+    qp_line: Vec<u8>,
 }
 
 pub fn nearest_multiple_of(n: usize, multiple: usize) -> usize {
-    if n % multiple == 0 {
+    if n.is_multiple_of(multiple) {
         n
     } else {
         (n / multiple + 1) * multiple
     }
 }
 
+/// Picks a buffer size for data of a known length, scaling with the length.
+///
+/// Data smaller than the minimum is read in a single chunk of exactly its length.
+pub(crate) fn auto_buf_size(len: usize) -> usize {
+    (len / 16)
+        .clamp(MIN_AUTO_CHUNK_SIZE, MAX_AUTO_CHUNK_SIZE)
+        .min(len)
+}
+
 impl<R: AsyncBufRead + Unpin + Send + Sync> ReaderStream<R> {
     pub(crate) fn new(reader: R, buf_size: Option<usize>, encoding: Option<Encoding>) -> Self {
-        let mut buf_size = buf_size.unwrap_or(CHUNK_SIZE);
-        if let Some(Encoding::Base64) = encoding {
-            // Base64 encoding requires a buffer size that is a multiple of 3
-            buf_size = nearest_multiple_of(buf_size, 3);
+        // A zero-sized buffer would read nothing and look like EOF
+        let mut buf_size = buf_size.unwrap_or(CHUNK_SIZE).max(1);
+        if encoding == Some(Encoding::Base64) {
+            // Only full lines can be encoded independently
+            buf_size = nearest_multiple_of(buf_size, crate::encoding::BASE64_LINE_LEN);
+        } else if encoding.is_some_and(Encoding::is_base64) {
+            // Base64 encoding requires a buffer size that is a non-zero multiple of 3
+            buf_size = nearest_multiple_of(buf_size.max(3), 3);
+        }
+        #[cfg(feature = "uuencode")]
+        if encoding == Some(Encoding::UuEncode) {
+            // Only full lines can be encoded independently
+            buf_size = nearest_multiple_of(buf_size, crate::encoding::UU_LINE_LEN);
         }
         Self {
             reader,
             buf_size,
             encoding,
-            buf_buffer: None,
+            buf_buffer: Vec::new(),
+            buf_pos: 0,
+            started: false,
+            finished: false,
+            pending: Vec::new(),
+            qp_line: Vec::new(),
+        }
+    }
+
+    /// Whether chunks must be exactly `buf_size` long (except the last) to be encoded independently.
+    fn needs_full_chunks(&self) -> bool {
+        match self.encoding {
+            Some(encoding) if encoding.is_base64() => true,
+            #[cfg(feature = "uuencode")]
+            Some(Encoding::UuEncode) => true,
+            _ => false,
+        }
+    }
+
+    /// Polls the next chunk of raw data, or `None` at EOF.
+    ///
+    /// Readers may return fewer bytes than asked for, for example at the end of a `BufReader`'s
+    /// buffer. For encodings that need whole chunks, reads are repeated until the chunk is full
+    /// or the data ends, so a short read never misaligns the encoded output.
+    fn poll_chunk(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<Vec<u8>>>> {
+        loop {
+            let start = self.pending.len();
+            self.pending.resize(self.buf_size, 0);
+            let result = poll_read_retry(&mut self.reader, cx, &mut self.pending[start..]);
+            let read = match result {
+                Poll::Ready(Ok(n)) => n,
+                Poll::Ready(Err(e)) => {
+                    self.pending.truncate(start);
+                    return Poll::Ready(Err(e));
+                }
+                Poll::Pending => {
+                    // Keep what was read so far for the next poll
+                    self.pending.truncate(start);
+                    return Poll::Pending;
+                }
+            };
+            self.pending.truncate(start + read);
+            let eof = read == 0;
+            if eof || !self.needs_full_chunks() || self.pending.len() == self.buf_size {
+                let chunk = std::mem::take(&mut self.pending);
+                return Poll::Ready(Ok((!chunk.is_empty()).then_some(chunk)));
+            }
+        }
+    }
+
+    /// Polls the next encoded chunk, or `None` once everything has been yielded.
+    ///
+    /// Chunks that encode to nothing yet, such as a partial quoted-printable line, are skipped
+    /// rather than yielded empty, since an empty chunk would look like the end of the data.
+    fn poll_encoded(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<Vec<u8>>>> {
+        loop {
+            let chunk = match self.poll_chunk(cx) {
+                Poll::Ready(Ok(chunk)) => chunk,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            let eof = chunk.is_none();
+            match self.encode_chunk(chunk) {
+                Some(encoded) => return Poll::Ready(Ok(Some(encoded))),
+                None if eof => return Poll::Ready(Ok(None)),
+                None => continue,
+            }
+        }
+    }
+
+    /// Encodes a chunk read from the reader, or finishes the data at EOF (`None`),
+    /// adding the framing of the encoding if it has any.
+    ///
+    /// Returns `None` once there is nothing left to yield.
+    fn encode_chunk(&mut self, chunk: Option<Vec<u8>>) -> Option<Vec<u8>> {
+        let Some(encoding) = self.encoding else {
+            return chunk;
+        };
+        let mut output = Vec::new();
+        if self.started {
+            if chunk.is_some() {
+                output.extend_from_slice(encoding.chunk_separator());
+            }
+        } else {
+            self.started = true;
+            output.extend_from_slice(encoding.prefix());
+        }
+        match chunk {
+            Some(chunk) if encoding == Encoding::QuotedPrintable => {
+                // Lines are usually short, only very long ones are split with soft line breaks
+                let max_line = self.buf_size.max(MIN_AUTO_CHUNK_SIZE);
+                output.extend(encode_qp_chunk(&mut self.qp_line, &chunk, max_line));
+            }
+            Some(mut chunk) => {
+                encoding.encode_chunk(&mut chunk);
+                output.extend(chunk);
+            }
+            None if self.finished => return None,
+            None => {
+                self.finished = true;
+                if !self.qp_line.is_empty() {
+                    let mut line = std::mem::take(&mut self.qp_line);
+                    encoding.encode(&mut line);
+                    output.extend(line);
+                }
+                output.extend_from_slice(encoding.suffix());
+            }
+        }
+        (!output.is_empty()).then_some(output)
+    }
+}
+
+/// Polls a read from the reader, retrying reads that were interrupted.
+///
+/// `Interrupted` is conventionally retryable (e.g. a signal arrived mid-read),
+/// so it shouldn't abort the whole upload. Some readers signal that they are not ready with
+/// `WouldBlock` instead of `Poll::Pending`, which is treated as pending, with a wake-up
+/// scheduled since the reader didn't register one.
+fn poll_read_retry<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    cx: &mut Context<'_>,
+    buf: &mut [u8],
+) -> Poll<io::Result<usize>> {
+    loop {
+        match Pin::new(&mut *reader).poll_read(cx, buf) {
+            Poll::Ready(Err(e)) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Poll::Ready(Err(e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            other => return other,
         }
     }
 }
@@ -43,22 +205,9 @@ impl<R: AsyncBufRead + Unpin + Send + Sync> ReaderStream<R> {
 impl<R: AsyncBufRead + Unpin + Send + Sync> Stream for ReaderStream<R> {
     type Item = StreamChunk;
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let buf_size = self.buf_size;
-        let mut buf = vec![0; buf_size];
         let this = &mut self;
-        let reader = Pin::new(&mut this.reader);
-
-        match reader.poll_read(cx, &mut buf) {
-            Poll::Ready(Ok(0)) => Poll::Ready(None), // EOF
-            Poll::Ready(Ok(n)) => {
-                buf.truncate(n); // Resize to actual bytes read
-                if let Some(encoding) = this.encoding {
-                    encoding.encode(&mut buf);
-                    Poll::Ready(Some(Ok(buf)))
-                } else {
-                    Poll::Ready(Some(Ok(buf)))
-                }
-            }
+        match this.poll_encoded(cx) {
+            Poll::Ready(Ok(chunk)) => Poll::Ready(chunk.map(Ok)),
             Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
             Poll::Pending => Poll::Pending,
         }
@@ -71,33 +220,22 @@ impl<R: AsyncBufRead + Unpin + Send + Sync> AsyncRead for ReaderStream<R> {
         cx: &mut Context<'_>,
         buf: &mut [u8],
     ) -> Poll<std::io::Result<usize>> {
-        let this = self.get_mut();
+        let mut this = self.get_mut();
         if this.encoding.is_some() {
-            // When encoding is needed, we need to use the stream implementation
-            // and cannot directly pass through to the reader
-            let mut temp_buf = vec![0; buf.len()];
-            let reader = Pin::new(&mut this.reader);
-            match reader.poll_read(cx, &mut temp_buf) {
-                Poll::Ready(Ok(0)) => Poll::Ready(Ok(0)), // EOF
-                Poll::Ready(Ok(n)) => {
-                    temp_buf.truncate(n); // Resize to actual bytes read
-                    if let Some(encoding) = &this.encoding {
-                        encoding.encode(&mut temp_buf);
-                        let copy_size = std::cmp::min(temp_buf.len(), buf.len());
-                        buf[..copy_size].copy_from_slice(&temp_buf[..copy_size]);
-                        Poll::Ready(Ok(copy_size))
-                    } else {
-                        let copy_size = std::cmp::min(n, buf.len());
-                        buf[..copy_size].copy_from_slice(&temp_buf[..copy_size]);
-                        Poll::Ready(Ok(copy_size))
-                    }
-                }
-                other => other,
-            }
+            // Encoded chunks can be larger than `buf`, so they go through the same buffer
+            // as `poll_fill_buf` and the rest is kept for the next read
+            let available = match Pin::new(&mut this).poll_fill_buf(cx) {
+                Poll::Ready(Ok(available)) => available,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            let copy_size = available.len().min(buf.len());
+            buf[..copy_size].copy_from_slice(&available[..copy_size]);
+            Pin::new(this).consume(copy_size);
+            Poll::Ready(Ok(copy_size))
         } else {
             // When no encoding is needed, pass through directly
-            let reader = Pin::new(&mut this.reader);
-            reader.poll_read(cx, buf)
+            poll_read_retry(&mut this.reader, cx, buf)
         }
     }
 }
@@ -111,27 +249,375 @@ impl<R: AsyncBufRead + Unpin + Send + Sync> AsyncBufRead for ReaderStream<R> {
         let this = self.get_mut();
         if this.encoding.is_none() {
             let reader = Pin::new(&mut this.reader);
-            return reader.poll_fill_buf(cx);
-        }
-        let buf_size = this.buf_size;
-        let buf = this.buf_buffer.get_or_insert_with(|| vec![0; buf_size]);
-        let reader = Pin::new(&mut this.reader);
-        match reader.poll_read(cx, buf) {
-            Poll::Ready(Ok(0)) => Poll::Ready(Ok(&[])), // EOF
-            Poll::Ready(Ok(n)) => {
-                buf.truncate(n);
-                let encoding = this.encoding.unwrap();
-                encoding.encode(buf);
-                Poll::Ready(Ok(this.buf_buffer.as_ref().unwrap()))
-            }
-            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
-            Poll::Pending => Poll::Pending,
+            return match reader.poll_fill_buf(cx) {
+                Poll::Ready(Err(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+                    ) =>
+                {
+                    // Ask to be polled again rather than failing the read
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+                other => other,
+            };
         }
+        // Hand out the rest of the current chunk before encoding the next one
+        if this.buf_pos == this.buf_buffer.len() {
+            let encoded = match this.poll_encoded(cx) {
+                Poll::Ready(Ok(chunk)) => chunk,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            this.buf_buffer = encoded.unwrap_or_default();
+            this.buf_pos = 0;
+        }
+        Poll::Ready(Ok(&this.buf_buffer[this.buf_pos..]))
     }
 
     fn consume(self: Pin<&mut Self>, amt: usize) {
         let this = self.get_mut();
-        let reader = Pin::new(&mut this.reader);
-        reader.consume(amt)
+        if this.encoding.is_none() {
+            Pin::new(&mut this.reader).consume(amt);
+        } else {
+            // The reader's own buffer was already drained into the encoded chunk
+            this.buf_pos = (this.buf_pos + amt).min(this.buf_buffer.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::{io::Cursor, AsyncReadExt, StreamExt};
+
+    /// A reader that fails with an error of the given kind once before yielding its data.
+    struct FailOnce {
+        kind: io::ErrorKind,
+        failed: bool,
+        inner: Cursor<Vec<u8>>,
+    }
+
+    impl FailOnce {
+        fn new(data: &[u8], kind: io::ErrorKind) -> Self {
+            Self {
+                kind,
+                failed: false,
+                inner: Cursor::new(data.to_vec()),
+            }
+        }
+
+        fn fail(&mut self) -> Option<io::Error> {
+            (!std::mem::replace(&mut self.failed, true)).then(|| self.kind.into())
+        }
+    }
+
+    impl AsyncRead for FailOnce {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            if let Some(e) = self.fail() {
+                return Poll::Ready(Err(e));
+            }
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncBufRead for FailOnce {
+        fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+            let this = self.get_mut();
+            if let Some(e) = this.fail() {
+                return Poll::Ready(Err(e));
+            }
+            Pin::new(&mut this.inner).poll_fill_buf(cx)
+        }
+
+        fn consume(mut self: Pin<&mut Self>, amt: usize) {
+            Pin::new(&mut self.inner).consume(amt)
+        }
+    }
+
+    #[test]
+    fn test_auto_buf_size() {
+        assert_eq!(auto_buf_size(10), 10);
+        assert_eq!(auto_buf_size(10_000), MIN_AUTO_CHUNK_SIZE);
+        assert_eq!(auto_buf_size(1_600_000), 100_000);
+        assert_eq!(auto_buf_size(1 << 30), MAX_AUTO_CHUNK_SIZE);
+    }
+
+    #[async_std::test]
+    async fn test_base64_tiny_buffer() {
+        let data = (0..=255).cycle().take(1000).collect::<Vec<u8>>();
+        let encode = |buf_size| {
+            let stream = ReaderStream::new(Cursor::new(&data), buf_size, Some(Encoding::Base64));
+            assert!(stream.buf_size >= 3);
+            stream.fold(Vec::new(), |mut output, chunk| {
+                output.extend(chunk.unwrap());
+                output
+            })
+        };
+
+        let tiny = encode(Some(1)).await;
+        assert_eq!(tiny, encode(Some(0)).await);
+        assert_eq!(tiny, encode(Some(4096)).await);
+
+        let mut decoded = tiny;
+        Encoding::Base64.decode(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[async_std::test]
+    async fn test_base64_short_reads_fill_chunks() {
+        let data = (0..=255).cycle().take(1000).collect::<Vec<u8>>();
+        // Each read stops at the end of a 10 byte piece, which isn't a multiple of 3
+        let reader = data[10..].chunks(10).fold(
+            Box::new(Cursor::new(data[..10].to_vec()))
+                as Box<dyn AsyncBufRead + Unpin + Send + Sync>,
+            |reader, piece| Box::new(reader.chain(Cursor::new(piece.to_vec()))),
+        );
+        let stream = ReaderStream::new(reader, Some(300), Some(Encoding::Base64));
+        let chunks = stream.map(|chunk| chunk.unwrap()).collect::<Vec<_>>().await;
+        // The buffer rounds up to 342 bytes, six full lines of 76 characters
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].len(), 6 * 76 + 5 * 2);
+        assert_eq!(chunks[1].len(), 2 + 6 * 76 + 5 * 2);
+
+        let mut decoded = chunks.concat();
+        Encoding::Base64.decode(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[async_std::test]
+    async fn test_base64_small_buffers_concatenate_to_valid_base64() -> Result<(), io::Error> {
+        let path =
+            std::env::temp_dir().join(format!("base64-{}.bin", crate::random_alphanumeric(8)));
+        let data = (0..=255).cycle().take(10_001).collect::<Vec<u8>>();
+        std::fs::write(&path, &data)?;
+        let file =
+            futures_lite::io::BufReader::with_capacity(7, async_fs::File::open(&path).await?);
+        let stream = ReaderStream::new(file, Some(7), Some(Encoding::Base64));
+        let chunks = stream.collect::<Vec<_>>().await;
+        std::fs::remove_file(&path)?;
+
+        let mut decoded = chunks.into_iter().collect::<Result<Vec<_>, _>>()?.concat();
+        assert_eq!(decoded, crate::encode_all(&data, Encoding::Base64));
+        let lines = decoded.split(|b| *b == b'\n').collect::<Vec<_>>();
+        assert!(lines[..lines.len() - 1]
+            .iter()
+            .all(|line| line.len() == 77 && line.ends_with(b"\r")));
+        Encoding::Base64.decode(&mut decoded)?;
+        assert_eq!(decoded, data);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_stream_matches_encode_all() {
+        let data = (0..=255).cycle().take(1000).collect::<Vec<u8>>();
+        // Quoted-printable has no exact encoded length, it is tested separately
+        let encodings = [
+            Encoding::SevenBit,
+            Encoding::Base64,
+            Encoding::Base64UrlSafe,
+            Encoding::Base64UrlSafePadded,
+            #[cfg(feature = "uuencode")]
+            Encoding::UuEncode,
+        ];
+        for encoding in encodings {
+            let expected = crate::encode_all(&data, encoding);
+            assert_eq!(Some(expected.len()), encoding.encoded_len(data.len()));
+            for buf_size in [1, 7, 300, 4096] {
+                let stream = ReaderStream::new(Cursor::new(&data), Some(buf_size), Some(encoding));
+                let chunks = stream.map(|chunk| chunk.unwrap()).collect::<Vec<_>>().await;
+                assert_eq!(
+                    chunks.concat(),
+                    expected,
+                    "{:?} in {} byte chunks",
+                    encoding,
+                    buf_size
+                );
+            }
+        }
+    }
+
+    #[async_std::test]
+    async fn test_quoted_printable_trailing_whitespace_across_chunks() {
+        let encode = |data: Vec<u8>, buf_size| {
+            let stream = ReaderStream::new(
+                Cursor::new(data),
+                Some(buf_size),
+                Some(Encoding::QuotedPrintable),
+            );
+            stream.fold(String::new(), |mut output, chunk| {
+                output.push_str(std::str::from_utf8(&chunk.unwrap()).unwrap());
+                output
+            })
+        };
+
+        let data = "trailing spaces  \r\ntab\t\r\n".repeat(3) + &"long line ".repeat(30) + "end ";
+        let expected = crate::encode_all(data.as_bytes(), Encoding::QuotedPrintable);
+        for buf_size in [1, 2, 5, 16, 17, 18, 1024] {
+            // Chunks ending next to the whitespace don't change which whitespace is at a line end
+            let encoded = encode(data.clone().into_bytes(), buf_size).await;
+            assert!(encoded.starts_with("trailing spaces =20\r\ntab=09\r\n"));
+            assert!(encoded.ends_with("end=20"));
+            assert_eq!(encoded.as_bytes(), expected, "{} byte chunks", buf_size);
+        }
+
+        // A line longer than the chunk size is flushed with soft line breaks
+        let data = "word ".repeat(4000).into_bytes();
+        let encoded = encode(data.clone(), 100).await;
+        assert!(encoded.split("\r\n").all(|line| line.len() <= 76));
+        let mut decoded = encoded.into_bytes();
+        Encoding::QuotedPrintable.decode(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[cfg(feature = "uuencode")]
+    #[async_std::test]
+    async fn test_uuencode_stream_keeps_line_framing() {
+        let data = (0..=255).cycle().take(1000).collect::<Vec<u8>>();
+        let mut expected = data.clone();
+        Encoding::UuEncode.encode(&mut expected);
+
+        let stream = ReaderStream::new(Cursor::new(&data), Some(10), Some(Encoding::UuEncode));
+        let chunks = stream.map(|chunk| chunk.unwrap()).collect::<Vec<_>>().await;
+        assert!(chunks.len() > 2);
+        assert_eq!(chunks.concat(), expected);
+    }
+
+    /// Reads everything through `fill_buf` and `consume`, consuming a varying part of each buffer,
+    /// and sometimes filling twice in a row without consuming.
+    async fn read_buffered(mut reader: impl AsyncBufRead + Unpin) -> Vec<u8> {
+        use futures_lite::AsyncBufReadExt;
+        let mut output = Vec::new();
+        for step in 0.. {
+            let available = reader.fill_buf().await.unwrap().to_vec();
+            if available.is_empty() {
+                break;
+            }
+            // Filling again without consuming must return the same bytes
+            assert_eq!(reader.fill_buf().await.unwrap(), available);
+            let amt = available.len().min([1, 2, 3, 5, 8, 64][step % 6]);
+            output.extend_from_slice(&available[..amt]);
+            reader.consume(amt);
+        }
+        output
+    }
+
+    /// Reads everything through `read` with a buffer of `buf_len` bytes.
+    async fn read_small(mut reader: impl AsyncRead + Unpin, buf_len: usize) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut buf = vec![0; buf_len];
+        loop {
+            let n = reader.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            output.extend_from_slice(&buf[..n]);
+        }
+        output
+    }
+
+    #[async_std::test]
+    async fn test_buffered_reads_match_stream() {
+        let data = "line with trailing space \r\n".repeat(20).into_bytes();
+        let encodings = [
+            None,
+            Some(Encoding::Base64),
+            Some(Encoding::Base64UrlSafePadded),
+            Some(Encoding::QuotedPrintable),
+            #[cfg(feature = "uuencode")]
+            Some(Encoding::UuEncode),
+        ];
+        for encoding in encodings {
+            for buf_size in [1, 7, 64, 4096] {
+                let new = || ReaderStream::new(Cursor::new(data.clone()), Some(buf_size), encoding);
+                let expected = new()
+                    .map(|chunk| chunk.unwrap())
+                    .collect::<Vec<_>>()
+                    .await
+                    .concat();
+                let context = format!("{:?} in {} byte chunks", encoding, buf_size);
+                assert_eq!(read_buffered(new()).await, expected, "{}", context);
+                assert_eq!(read_small(new(), 5).await, expected, "{}", context);
+
+                let mut decoded = expected;
+                if let Some(encoding) = encoding {
+                    encoding.decode(&mut decoded).unwrap();
+                }
+                assert_eq!(decoded, data, "{}", context);
+            }
+        }
+    }
+
+    #[async_std::test]
+    async fn test_zero_buf_size_reads_data() {
+        let stream = ReaderStream::new(Cursor::new(b"abc"), Some(0), None);
+        let chunks = stream.map(|chunk| chunk.unwrap()).collect::<Vec<_>>().await;
+        assert_eq!(chunks.concat(), b"abc");
+
+        let empty = ReaderStream::new(Cursor::new(b""), Some(0), None);
+        assert_eq!(empty.count().await, 0);
+    }
+
+    #[async_std::test]
+    async fn test_stream_retries_interrupted_reads() {
+        let data = b"interrupted but complete";
+        let mut stream = ReaderStream::new(
+            FailOnce::new(data, io::ErrorKind::Interrupted),
+            Some(8),
+            None,
+        );
+        let mut output = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            output.extend(chunk.expect("interrupted read should be retried"));
+        }
+        assert_eq!(output, data);
+    }
+
+    #[async_std::test]
+    async fn test_reader_retries_interrupted_reads() {
+        let data = b"interrupted but complete";
+        let mut reader = ReaderStream::new(
+            FailOnce::new(data, io::ErrorKind::Interrupted),
+            Some(8),
+            None,
+        );
+        let mut output = Vec::new();
+        reader
+            .read_to_end(&mut output)
+            .await
+            .expect("interrupted read should be retried");
+        assert_eq!(output, data);
+    }
+
+    #[async_std::test]
+    async fn test_would_block_is_pending() {
+        let data = b"not ready yet, then complete";
+        for encoding in [None, Some(Encoding::Base64)] {
+            let reader = FailOnce::new(data, io::ErrorKind::WouldBlock);
+            let mut stream = ReaderStream::new(reader, Some(8), encoding);
+            let mut output = Vec::new();
+            while let Some(chunk) = stream.next().await {
+                output.extend(chunk.expect("WouldBlock should be retried"));
+            }
+            let mut expected = data.to_vec();
+            if let Some(encoding) = encoding {
+                expected = crate::encode_all(data, encoding);
+            }
+            assert_eq!(output, expected);
+
+            let reader = FailOnce::new(data, io::ErrorKind::WouldBlock);
+            let mut reader = ReaderStream::new(reader, Some(8), encoding);
+            let mut output = Vec::new();
+            reader
+                .read_to_end(&mut output)
+                .await
+                .expect("WouldBlock should be retried");
+            assert_eq!(output, expected);
+        }
     }
 }
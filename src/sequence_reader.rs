@@ -0,0 +1,91 @@
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_lite::{AsyncBufRead, AsyncRead};
+
+pub(crate) type Segment = Pin<Box<dyn AsyncBufRead + Send + Sync>>;
+
+/// Reads several readers one after another.
+///
+/// Unlike nesting `chain` calls, polling only ever touches the current reader, so reads don't get
+/// deeper or slower as the number of readers grows. Each reader is dropped once it reaches EOF.
+pub(crate) struct SequenceReader {
+    segments: VecDeque<Segment>,
+}
+
+impl SequenceReader {
+    pub(crate) fn new(segments: impl IntoIterator<Item = Segment>) -> Self {
+        Self {
+            segments: segments.into_iter().collect(),
+        }
+    }
+}
+
+impl AsyncBufRead for SequenceReader {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        // Skip the readers that are done
+        loop {
+            let Some(segment) = this.segments.front_mut() else {
+                return Poll::Ready(Ok(&[]));
+            };
+            match segment.as_mut().poll_fill_buf(cx) {
+                Poll::Ready(Ok([])) => {}
+                Poll::Ready(Ok(_)) => break,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+            this.segments.pop_front();
+        }
+        // The data is buffered now, so this returns it without reading again
+        this.segments[0].as_mut().poll_fill_buf(cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        if let Some(segment) = self.get_mut().segments.front_mut() {
+            segment.as_mut().consume(amt);
+        }
+    }
+}
+
+impl AsyncRead for SequenceReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let available = match self.as_mut().poll_fill_buf(cx) {
+            Poll::Ready(Ok(available)) => available,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        };
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Poll::Ready(Ok(len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::{io::Cursor, AsyncReadExt};
+
+    #[async_std::test]
+    async fn test_reads_segments_in_order() {
+        let segments = ["first", "", "second", "", ""]
+            .into_iter()
+            .map(|s| Box::pin(Cursor::new(s.as_bytes().to_vec())) as Segment);
+        let mut reader = SequenceReader::new(segments);
+        let mut output = String::new();
+        reader.read_to_string(&mut output).await.unwrap();
+        assert_eq!(output, "firstsecond");
+
+        let mut empty = SequenceReader::new([]);
+        assert_eq!(empty.read(&mut [0; 4]).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    io::{self, SeekFrom},
+    path::PathBuf,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_lite::{io::BufReader, AsyncRead, AsyncSeekExt, AsyncWriteExt, Stream, StreamExt};
+use http_types::Body;
+
+use crate::{random_alphanumeric, StreamChunk};
+
+/// Forms larger than this are spooled to a temporary file rather than kept in memory.
+pub(crate) const SPOOL_MEMORY_LIMIT: usize = 8 * 1024 * 1024;
+
+/// A temporary file that is deleted once the reader is dropped.
+struct SpooledFile {
+    file: Option<async_fs::File>,
+    path: PathBuf,
+}
+
+impl AsyncRead for SpooledFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match &mut self.get_mut().file {
+            Some(file) => Pin::new(file).poll_read(cx, buf),
+            None => Poll::Ready(Ok(0)),
+        }
+    }
+}
+
+impl Drop for SpooledFile {
+    fn drop(&mut self) {
+        // Close the file first, open files can't be deleted on every platform
+        drop(self.file.take());
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Reads a stream to the end, counting its length, and returns a body of the exact length.
+///
+/// Data is kept in memory up to `memory_limit` bytes, beyond which everything is written to a
+/// temporary file instead, so a large form is never held in memory twice.
+pub(crate) async fn spool(
+    stream: impl Stream<Item = StreamChunk>,
+    memory_limit: usize,
+) -> io::Result<Body> {
+    let mut stream = Box::pin(stream);
+    let mut buffer = Vec::new();
+    let mut spooled: Option<SpooledFile> = None;
+    let mut len = 0u64;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        len += chunk.len() as u64;
+        match &mut spooled {
+            Some(SpooledFile {
+                file: Some(file), ..
+            }) => file.write_all(&chunk).await?,
+            _ => {
+                buffer.extend_from_slice(&chunk);
+                if buffer.len() > memory_limit {
+                    let path = std::env::temp_dir()
+                        .join(format!("multipart-spool-{}", random_alphanumeric(16)));
+                    let file = async_fs::OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .create_new(true)
+                        .open(&path)
+                        .await?;
+                    // Owned from here on, so the file is deleted even if spooling fails
+                    let spooled = spooled.insert(SpooledFile {
+                        file: Some(file),
+                        path,
+                    });
+                    let file = spooled.file.as_mut().unwrap();
+                    file.write_all(&buffer).await?;
+                    buffer = Vec::new();
+                }
+            }
+        }
+    }
+
+    let Some(mut spooled) = spooled else {
+        return Ok(Body::from(buffer));
+    };
+    let file = spooled.file.as_mut().unwrap();
+    file.flush().await?;
+    file.seek(SeekFrom::Start(0)).await?;
+    Ok(Body::from_reader(
+        BufReader::new(spooled),
+        Some(len as usize),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(data: &[u8]) -> impl Stream<Item = StreamChunk> {
+        let chunks = data.chunks(10).map(|c| Ok(c.to_vec())).collect::<Vec<_>>();
+        futures_lite::stream::iter(chunks)
+    }
+
+    #[async_std::test]
+    async fn test_spool_in_memory() -> io::Result<()> {
+        let data = (0..=255).collect::<Vec<u8>>();
+        let body = spool(chunks(&data), 1024).await?;
+        assert_eq!(body.len(), Some(data.len()));
+        assert_eq!(body.into_bytes().await.unwrap(), data);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_spool_to_file() -> io::Result<()> {
+        let data = (0..=255).cycle().take(5000).collect::<Vec<u8>>();
+        let body = spool(chunks(&data), 100).await?;
+        assert_eq!(body.len(), Some(data.len()));
+        assert_eq!(body.into_bytes().await.unwrap(), data);
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+//! Integration with the [`surf`](https://crates.io/crates/surf) client.
+
+use surf::RequestBuilder;
+
+use crate::{Multipart, RequestMultipartExt};
+
+/// Extension trait for attaching a multipart form to a [`surf::RequestBuilder`].
+pub trait RequestBuilderMultipartExt {
+    /// Sets the form as the request body, along with its `Content-Type` and, when the length is known,
+    /// `Content-Length` headers.
+    fn multipart(self, multipart: Multipart<'_>) -> Self;
+}
+
+impl RequestBuilderMultipartExt for RequestBuilder {
+    fn multipart(self, multipart: Multipart<'_>) -> Self {
+        let headers = multipart.request_headers();
+        let mut builder = self.body(multipart.into_body(None));
+        for (name, value) in headers {
+            builder = builder.header(name.as_str(), value);
+        }
+        builder
+    }
+}
+
+impl RequestMultipartExt for surf::Request {
+    fn multipart(&mut self, multipart: Multipart) {
+        multipart.set_request(self.as_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use surf::{
+        http::{headers::CONTENT_TYPE, Method},
+        Url,
+    };
+
+    #[async_std::test]
+    async fn test_surf_request_builder() -> surf::Result<()> {
+        let mut multipart = Multipart::new();
+        multipart.add_text("name", "John Doe");
+        let content_type = multipart.content_type();
+
+        let mut req = RequestBuilder::new(Method::Post, Url::parse("http://example.com")?)
+            .multipart(multipart)
+            .build();
+        assert_eq!(req.header(CONTENT_TYPE).unwrap().as_str(), content_type);
+
+        let body = req.take_body().into_string().await?;
+        assert!(body.contains("name=\"name\""));
+        assert!(body.contains("John Doe"));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_surf_request() -> surf::Result<()> {
+        let mut multipart = Multipart::new();
+        multipart.add_text("name", "John Doe");
+
+        let mut req = RequestBuilder::new(Method::Post, Url::parse("http://example.com")?).build();
+        req.multipart(multipart);
+        let content_type = req.header(CONTENT_TYPE).unwrap().as_str();
+        assert!(content_type.starts_with("multipart/form-data; boundary="));
+
+        let body = req.take_body().into_string().await?;
+        assert!(body.contains("John Doe"));
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+//! Helpers for checking serialized forms in tests.
+
+use std::borrow::Cow;
+
+use http_types::{format_err, Result};
+use mime_guess::Mime;
+
+use crate::{parser, Multipart};
+
+/// A part expected in a serialized form, see [`Multipart::verify_against`].
+///
+/// ```
+/// use http_client_multipart::ExpectedPart;
+///
+/// let part = ExpectedPart::new("avatar", "...")
+///     .with_filename("avatar.png")
+///     .with_content_type("image/png");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedPart<'a> {
+    name: Cow<'a, str>,
+    body: Cow<'a, [u8]>,
+    filename: Option<Cow<'a, str>>,
+    content_type: Option<Cow<'a, str>>,
+}
+
+impl<'a> ExpectedPart<'a> {
+    /// Expects a part named `name` whose decoded body is `body`.
+    pub fn new(name: impl Into<Cow<'a, str>>, body: impl AsRef<[u8]>) -> Self {
+        Self {
+            name: name.into(),
+            body: Cow::Owned(body.as_ref().to_vec()),
+            filename: None,
+            content_type: None,
+        }
+    }
+
+    /// Also expects the part to have this filename.
+    pub fn with_filename(mut self, filename: impl Into<Cow<'a, str>>) -> Self {
+        self.filename = Some(filename.into());
+        self
+    }
+
+    /// Also expects the part to have this content type, before transfer encoding.
+    pub fn with_content_type(mut self, content_type: impl Into<Cow<'a, str>>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+}
+
+impl Multipart<'_> {
+    /// Serializes the form and checks that it is framed by `expected_boundary` and holds exactly
+    /// `expected_parts`, in order.
+    ///
+    /// Each part's name, decoded body, and the filename and content type if given, must match.
+    /// Returns an error describing the first mismatch, so tests can use it with `?`.
+    pub async fn verify_against(
+        self,
+        expected_boundary: &str,
+        expected_parts: &[ExpectedPart<'_>],
+    ) -> Result<()> {
+        if self.boundary != expected_boundary {
+            return Err(format_err!(
+                "Form boundary is {:?}, expected {:?}",
+                self.boundary,
+                expected_boundary
+            ));
+        }
+        let bytes = self.into_bytes().await?;
+        if expected_parts.is_empty() && bytes.is_empty() {
+            return Ok(());
+        }
+        let closing = format!("--{}--", expected_boundary);
+        if !bytes
+            .windows(closing.len())
+            .any(|window| window == closing.as_bytes())
+        {
+            return Err(format_err!("Serialized form has no closing boundary"));
+        }
+
+        let parts = parser::parse(&bytes, expected_boundary)?;
+        if parts.len() != expected_parts.len() {
+            return Err(format_err!(
+                "Form has {} parts, expected {}",
+                parts.len(),
+                expected_parts.len()
+            ));
+        }
+        for (i, (part, expected)) in parts.iter().zip(expected_parts).enumerate() {
+            if part.name() != Some(&*expected.name) {
+                return Err(format_err!(
+                    "Part {} is named {:?}, expected {:?}",
+                    i,
+                    part.name(),
+                    expected.name
+                ));
+            }
+            if let Some(filename) = &expected.filename {
+                if part.filename() != Some(&**filename) {
+                    return Err(format_err!(
+                        "Part {:?} has filename {:?}, expected {:?}",
+                        expected.name,
+                        part.filename(),
+                        filename
+                    ));
+                }
+            }
+            if let Some(content_type) = &expected.content_type {
+                let content_type: Mime = content_type.parse()?;
+                if *part.decoded_content_type() != content_type {
+                    return Err(format_err!(
+                        "Part {:?} has content type {}, expected {}",
+                        expected.name,
+                        part.decoded_content_type(),
+                        content_type
+                    ));
+                }
+            }
+            let body = part.data.as_bytes().unwrap_or_default();
+            if body != &*expected.body {
+                return Err(format_err!(
+                    "Part {:?} has body {:?}, expected {:?}",
+                    expected.name,
+                    String::from_utf8_lossy(body),
+                    String::from_utf8_lossy(&expected.body)
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Encoding;
+
+    fn mixed_form() -> Result<Multipart<'static>> {
+        let mut form = Multipart::new().with_boundary("verify-boundary")?;
+        form.add_text("name", "John Doe");
+        form.add_file_bytes(
+            "avatar",
+            "avatar.png",
+            [0x89, b'P', b'N', b'G', 0, 255],
+            "image/png",
+            Some(Encoding::Base64),
+        )?;
+        form.add_enc_text("note", "café", Encoding::QuotedPrintable);
+        Ok(form)
+    }
+
+    fn expected_parts() -> Vec<ExpectedPart<'static>> {
+        vec![
+            ExpectedPart::new("name", "John Doe"),
+            ExpectedPart::new("avatar", [0x89, b'P', b'N', b'G', 0, 255])
+                .with_filename("avatar.png")
+                .with_content_type("image/png"),
+            ExpectedPart::new("note", "café"),
+        ]
+    }
+
+    #[async_std::test]
+    async fn test_verify_against_mixed_form() -> Result<()> {
+        mixed_form()?
+            .verify_against("verify-boundary", &expected_parts())
+            .await
+    }
+
+    #[async_std::test]
+    async fn test_verify_against_mismatches() -> Result<()> {
+        let err = mixed_form()?
+            .verify_against("other-boundary", &expected_parts())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("boundary"));
+
+        let mut parts = expected_parts();
+        parts.swap(0, 2);
+        let err = mixed_form()?
+            .verify_against("verify-boundary", &parts)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Part 0 is named"));
+
+        let mut parts = expected_parts();
+        parts[1] = parts[1].clone().with_filename("other.png");
+        assert!(mixed_form()?
+            .verify_against("verify-boundary", &parts)
+            .await
+            .is_err());
+
+        let mut parts = expected_parts();
+        parts[2] = ExpectedPart::new("note", "cafe");
+        let err = mixed_form()?
+            .verify_against("verify-boundary", &parts)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("has body"));
+
+        parts.pop();
+        assert!(mixed_form()?
+            .verify_against("verify-boundary", &parts)
+            .await
+            .is_err());
+        Ok(())
+    }
+}
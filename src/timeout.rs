@@ -0,0 +1,128 @@
+//! Bounding how long each chunk of a form may take to read, using [`async-io`](https://crates.io/crates/async-io) timers.
+
+use std::{io, time::Duration};
+
+use async_io::Timer;
+use futures_lite::{future, Stream, StreamExt};
+
+use crate::{Multipart, StreamChunk};
+
+impl Multipart<'_> {
+    /// Converts the multipart form into a stream of byte chunks, failing if a single chunk takes
+    /// longer than `per_chunk` to read.
+    ///
+    /// A stalled source, such as a reader fed by an unresponsive network peer, would otherwise hang
+    /// the upload forever. On timeout a `TimedOut` error is yielded and the stream ends.
+    /// The output bytes are otherwise the same as [`Multipart::into_stream`].
+    pub fn into_stream_with_timeout(
+        self,
+        buf_size: Option<usize>,
+        per_chunk: Duration,
+    ) -> impl Stream<Item = StreamChunk> {
+        let stream = Box::pin(self.into_stream(buf_size));
+        futures_lite::stream::unfold(Some(stream), move |stream| async move {
+            let mut stream = stream?;
+            let next = async { Some(stream.next().await) };
+            let timeout = async {
+                Timer::after(per_chunk).await;
+                None
+            };
+            match future::or(next, timeout).await {
+                Some(Some(chunk)) => Some((chunk, Some(stream))),
+                Some(None) => None,
+                None => {
+                    let error = io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("Reading a chunk took longer than {:?}", per_chunk),
+                    );
+                    Some((Err(error), None))
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::{AsyncBufRead, AsyncRead};
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    /// A reader that never yields any data.
+    struct Stalled;
+
+    impl AsyncRead for Stalled {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Pending
+        }
+    }
+
+    impl AsyncBufRead for Stalled {
+        fn poll_fill_buf(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+            Poll::Pending
+        }
+
+        fn consume(self: Pin<&mut Self>, _amt: usize) {}
+    }
+
+    #[async_std::test]
+    async fn test_stalled_reader_times_out() {
+        let mut form = Multipart::new();
+        form.add_text("field", "value");
+        form.add_async_read(
+            "file",
+            "a.bin",
+            "application/octet-stream",
+            None,
+            Stalled,
+            None,
+        )
+        .unwrap();
+
+        let mut stream = Box::pin(form.into_stream_with_timeout(None, Duration::from_millis(50)));
+        let mut output = Vec::new();
+        let mut error = None;
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                Ok(chunk) => output.extend(chunk),
+                Err(e) => {
+                    error = Some(e);
+                    // The stream ends after the timeout
+                    assert!(stream.next().await.is_none());
+                    break;
+                }
+            }
+        }
+        assert_eq!(error.unwrap().kind(), io::ErrorKind::TimedOut);
+        // Everything before the stalled part was still sent
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("name=\"field\""));
+        assert!(output.contains("filename=\"a.bin\""));
+    }
+
+    #[async_std::test]
+    async fn test_timeout_passes_data_through() -> http_types::Result<()> {
+        let form = || {
+            let mut form = Multipart::new().with_boundary("b")?;
+            form.add_text("field", "value");
+            form.add_file_bytes("f", "a.txt", "hello", "text/plain", None)?;
+            http_types::Result::Ok(form)
+        };
+        let expected = form()?.into_bytes().await?;
+
+        let mut stream = Box::pin(form()?.into_stream_with_timeout(None, Duration::from_secs(5)));
+        let mut output = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            output.extend(chunk?);
+        }
+        assert_eq!(output, expected);
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+//! Integration with [`tokio`](https://crates.io/crates/tokio) I/O.
+
+use http_types::Result;
+use tokio_util::compat::TokioAsyncWriteCompatExt;
+
+use crate::Multipart;
+
+impl Multipart<'_> {
+    /// Streams the multipart form into a Tokio writer, returning the number of bytes written.
+    ///
+    /// Behaves like [`Multipart::write_to`], which it wraps through the `tokio-util` compat layer.
+    pub async fn write_to_tokio<W: tokio::io::AsyncWrite + Unpin>(self, writer: W) -> Result<u64> {
+        self.write_to(writer.compat_write()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn test_write_to_tokio() -> Result<()> {
+        let form = || -> Result<Multipart<'static>> {
+            let mut form = Multipart::new().with_boundary("b")?;
+            form.add_text("field", "value");
+            form.add_file_bytes("f", "a.txt", "hello", "text/plain", None)?;
+            Ok(form)
+        };
+        let expected = form()?.into_bytes().await?;
+
+        let mut written = Vec::new();
+        let len = form()?.write_to_tokio(&mut written).await?;
+        assert_eq!(written, expected);
+        assert_eq!(len, expected.len() as u64);
+        Ok(())
+    }
+}